@@ -1,57 +1,513 @@
-use crate::semaphore::Semaphore;
-use std::collections::LinkedList;
+use crate::ringbuf::RingBuffer;
+use crate::select::{Operation, Signal};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+// sendのエラー。受信端が破棄されており、送信できなかったデータを返す
+#[derive(Debug, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+// recvのエラー。すべての送信端が破棄されており、キューも空
+#[derive(Debug, PartialEq, Eq)]
+pub struct RecvError;
+
+// try_sendのエラー。送信できなかったデータを返す
+#[derive(Debug, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    Full(T),         // キューが満杯
+    Disconnected(T), // 受信端が破棄された
+}
+
+// send_timeoutのエラー。送信できなかったデータを返す
+#[derive(Debug, PartialEq, Eq)]
+pub enum SendTimeoutError<T> {
+    Timeout(T),      // タイムアウト
+    Disconnected(T), // 受信端が破棄された
+}
+
+// try_recvのエラー
+#[derive(Debug, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,        // キューが空
+    Disconnected, // すべての送信端が破棄された
+}
+
+// recv_timeoutのエラー
+#[derive(Debug, PartialEq, Eq)]
+pub enum RecvTimeoutError {
+    Timeout,      // タイムアウト
+    Disconnected, // すべての送信端が破棄された
+}
+
+// ロックで保護される状態
+struct State<T> {
+    buf: RingBuffer<T>, // キュー
+    senders: usize,     // 送信端の数
+    receivers: usize,   // 受信端の数
+    // 受信待ちの受信端。到着順に並び、先頭から順に受信する
+    waiters: VecDeque<(usize, Waiter)>,
+    next_waiter: usize, // 受信待ちの識別番号
+    send_wakers: Vec<Waker>, // 送信待ちの非同期タスク
+    selectors: Vec<Arc<Signal>>, // 状態の変化を待機中のSelect
+}
+
+// 受信待ちの受信端の起床方法
+enum Waiter {
+    Thread(Arc<Condvar>), // スレッドは条件変数で待機
+    Task(Waker),          // 非同期タスクはWakerで起床
+}
+
+impl Waiter {
+    fn wake(&self) {
+        match self {
+            Waiter::Thread(cond) => cond.notify_all(),
+            Waiter::Task(waker) => waker.wake_by_ref(),
+        }
+    }
+}
+
+impl<T> State<T> {
+    // 受信待ちの末尾に並び、識別番号をリターン
+    fn push_waiter(&mut self, waiter: Waiter) -> usize {
+        let id = self.next_waiter;
+        self.next_waiter = self.next_waiter.wrapping_add(1);
+        self.waiters.push_back((id, waiter));
+        id
+    }
+
+    fn is_front(&self, id: usize) -> bool {
+        matches!(self.waiters.front(), Some((n, _)) if *n == id)
+    }
+
+    // 受信待ちから外す。先頭だった場合はtrueをリターン
+    fn remove_waiter(&mut self, id: usize) -> bool {
+        let pos = self.waiters.iter().position(|(n, _)| *n == id).unwrap();
+        self.waiters.remove(pos);
+        pos == 0
+    }
+
+    // 受信待ちの先頭の受信端を起床
+    fn wake_receiver(&self) {
+        if let Some((_, waiter)) = self.waiters.front() {
+            waiter.wake();
+        }
+    }
+
+    // 送信待ちの非同期タスクをすべて起床
+    fn wake_senders(&mut self) {
+        for waker in self.send_wakers.drain(..) {
+            waker.wake();
+        }
+    }
+
+    // 状態の変化を待機中のSelectへ通知
+    fn notify_selectors(&self) {
+        for signal in self.selectors.iter() {
+            signal.notify();
+        }
+    }
+
+    fn watch(&mut self, signal: &Arc<Signal>) {
+        self.selectors.push(signal.clone());
+    }
+
+    fn unwatch(&mut self, signal: &Arc<Signal>) {
+        self.selectors.retain(|s| !Arc::ptr_eq(s, signal));
+    }
+}
+
+// 送信端と受信端で共有する型
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    not_full: Condvar, // 書き込み側の条件変数
+}
 
 // 送信端のための型 <1>
-#[derive(Clone)]
 pub struct Sender<T> {
-    sem: Arc<Semaphore>, // 有限性を実現するセマフォ
-    buf: Arc<Mutex<LinkedList<T>>>, // キュー
-    cond: Arc<Condvar>, // 読み込み側の条件変数
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        let mut state = self.shared.state.lock().unwrap();
+        state.senders += 1;
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.senders -= 1;
+        // 最後の送信端なら、待機中の受信側をすべて起床
+        if state.senders == 0 {
+            for (_, waiter) in state.waiters.iter() {
+                waiter.wake();
+            }
+            state.notify_selectors();
+        }
+    }
 }
 
 impl<T: Send> Sender<T> { // <2>
-    // 送信関数
-    pub fn send(&self, data: T) {
-        self.sem.wait(); // キューの最大値に到達したら待機 <3>
-        let mut buf = self.buf.lock().unwrap();
-        buf.push_back(data); // エンキュー
-        self.cond.notify_one(); // 読み込み側へ通知 <4>
+    // 送信関数。受信端が破棄されている場合はエラー
+    pub fn send(&self, data: T) -> Result<(), SendError<T>> {
+        let state = self.shared.state.lock().unwrap();
+        // キューの最大値に到達したら待機 <3>
+        let mut state = self
+            .shared
+            .not_full
+            .wait_while(state, |s| s.buf.is_full() && s.receivers > 0)
+            .unwrap();
+        if state.receivers == 0 {
+            return Err(SendError(data));
+        }
+        self.enqueue(&mut state, data);
+        Ok(())
+    }
+
+    // 待機せずに送信。キューが満杯の場合はエラー
+    pub fn try_send(&self, data: T) -> Result<(), TrySendError<T>> {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.receivers == 0 {
+            return Err(TrySendError::Disconnected(data));
+        }
+        if state.buf.is_full() {
+            return Err(TrySendError::Full(data));
+        }
+        self.enqueue(&mut state, data);
+        Ok(())
+    }
+
+    // 最大でdurだけ待機して送信。タイムアウトした場合はエラー
+    pub fn send_timeout(&self, data: T, dur: Duration) -> Result<(), SendTimeoutError<T>> {
+        let state = self.shared.state.lock().unwrap();
+        let (mut state, _) = self
+            .shared
+            .not_full
+            .wait_timeout_while(state, dur, |s| s.buf.is_full() && s.receivers > 0)
+            .unwrap();
+        if state.receivers == 0 {
+            return Err(SendTimeoutError::Disconnected(data));
+        }
+        if state.buf.is_full() {
+            return Err(SendTimeoutError::Timeout(data));
+        }
+        self.enqueue(&mut state, data);
+        Ok(())
+    }
+
+    // 非同期送信関数。キューが満杯の場合はスレッドをブロックせずに待機
+    pub fn send_async(&self, data: T) -> SendFuture<'_, T> {
+        SendFuture {
+            tx: self,
+            data: Some(data),
+        }
+    }
+
+    fn enqueue(&self, state: &mut State<T>, data: T) {
+        // 満杯でないことは確認済み
+        let _ = state.buf.push_back(data); // エンキュー
+        state.wake_receiver(); // 読み込み側へ通知 <4>
+        state.notify_selectors();
+    }
+}
+
+impl<T> Operation for Sender<T> {
+    fn is_ready(&self) -> bool {
+        let state = self.shared.state.lock().unwrap();
+        !state.buf.is_full() || state.receivers == 0
+    }
+
+    fn watch(&self, signal: &Arc<Signal>) {
+        self.shared.state.lock().unwrap().watch(signal);
+    }
+
+    fn unwatch(&self, signal: &Arc<Signal>) {
+        self.shared.state.lock().unwrap().unwatch(signal);
     }
 }
 
 // 受信端のための型 <1>
+// クローンすることで、複数のスレッドから受信可能
 pub struct Receiver<T> {
-    sem: Arc<Semaphore>, // 有限性を実現するセマフォ
-    buf: Arc<Mutex<LinkedList<T>>>, // キュー
-    cond: Arc<Condvar>, // 読み込み側の条件変数
+    shared: Arc<Shared<T>>,
+    cond: Arc<Condvar>, // 受信待ちの際に待機する条件変数
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        let mut state = self.shared.state.lock().unwrap();
+        state.receivers += 1;
+        Receiver {
+            shared: self.shared.clone(),
+            cond: Arc::new(Condvar::new()),
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.receivers -= 1;
+        // 最後の受信端なら、送信側で待機中のスレッドを起床し、以降の送信をエラーにする
+        if state.receivers == 0 {
+            self.shared.not_full.notify_all();
+            state.wake_senders();
+            state.notify_selectors();
+        }
+    }
 }
 
 impl<T> Receiver<T> {
-    pub fn recv(&self) -> T {
-        let mut buf = self.buf.lock().unwrap();
+    // 受信関数。すべての送信端が破棄され、キューが空の場合はエラー
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.recv_deadline(None).map_err(|_| RecvError)
+    }
+
+    // 待機せずに受信。キューが空の場合はエラー
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.state.lock().unwrap();
+        // 受信待ちの受信端がいる場合は、そちらを優先する
+        if state.waiters.is_empty() || state.senders == 0 {
+            if let Some(data) = self.dequeue(&mut state) {
+                return Ok(data);
+            }
+        }
+        if state.senders == 0 {
+            return Err(TryRecvError::Disconnected);
+        }
+        Err(TryRecvError::Empty)
+    }
+
+    // 最大でdurだけ待機して受信。タイムアウトした場合はエラー
+    pub fn recv_timeout(&self, dur: Duration) -> Result<T, RecvTimeoutError> {
+        self.recv_deadline(Some(Instant::now() + dur))
+    }
+
+    // deadlineまで待機して受信。Noneの場合は受信できるまで待機
+    fn recv_deadline(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        let mut state = self.shared.state.lock().unwrap();
+
+        // 受信待ちの受信端がいなければ、待機せずに取り出す
+        if state.waiters.is_empty() || state.senders == 0 {
+            if let Some(data) = self.dequeue(&mut state) {
+                return Ok(data);
+            }
+        }
+        if state.senders == 0 {
+            return Err(RecvTimeoutError::Disconnected);
+        }
+
+        // 受信待ちの末尾に並ぶ
+        let id = state.push_waiter(Waiter::Thread(self.cond.clone()));
+
+        // 自身が先頭かつキューが空でなくなるまで待機 <4>
         loop {
-            // キューから取り出し <2>
-            if let Some(data) = buf.pop_front() {
-                self.sem.post(); // <3>
-                return data;
+            if (state.is_front(id) && !state.buf.is_empty()) || state.senders == 0 {
+                break;
+            }
+            match deadline {
+                None => state = self.cond.wait(state).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    state = self.cond.wait_timeout(state, deadline - now).unwrap().0;
+                }
             }
-            // 空の場合待機 <4>
-            buf = self.cond.wait(buf).unwrap();
         }
+
+        match self.finish_wait(&mut state, id) {
+            Some(data) => Ok(data),
+            None if state.senders == 0 => Err(RecvTimeoutError::Disconnected),
+            None => Err(RecvTimeoutError::Timeout),
+        }
+    }
+
+    // 非同期受信関数。キューが空の場合はスレッドをブロックせずに待機
+    pub fn recv_async(&self) -> RecvFuture<'_, T> {
+        RecvFuture { rx: self, id: None }
+    }
+
+    // 受信待ちから外し、先頭だった場合は取り出す
+    fn finish_wait(&self, state: &mut State<T>, id: usize) -> Option<T> {
+        let is_front = state.remove_waiter(id);
+        let data = if is_front || state.senders == 0 {
+            self.dequeue(state) // キューから取り出し <2>
+        } else {
+            None
+        };
+
+        // まだデータがあれば、次の受信端を起床
+        if !state.buf.is_empty() {
+            state.wake_receiver();
+            state.notify_selectors();
+        }
+        data
+    }
+
+    fn dequeue(&self, state: &mut State<T>) -> Option<T> {
+        let data = state.buf.pop_front()?;
+        self.shared.not_full.notify_one(); // 書き込み側へ通知 <3>
+        state.wake_senders();
+        state.notify_selectors();
+        Some(data)
+    }
+}
+
+impl<T> Operation for Receiver<T> {
+    fn is_ready(&self) -> bool {
+        let state = self.shared.state.lock().unwrap();
+        // 受信待ちの受信端がいる場合は、そちらが優先される
+        (!state.buf.is_empty() && state.waiters.is_empty()) || state.senders == 0
+    }
+
+    fn watch(&self, signal: &Arc<Signal>) {
+        self.shared.state.lock().unwrap().watch(signal);
+    }
+
+    fn unwatch(&self, signal: &Arc<Signal>) {
+        self.shared.state.lock().unwrap().unwatch(signal);
+    }
+}
+
+// send_asyncがリターンするFuture
+pub struct SendFuture<'a, T> {
+    tx: &'a Sender<T>,
+    data: Option<T>, // 送信するデータ。送信後はNone
+}
+
+// dataはピン留めせずに取り出すため、Tに関わらずUnpinとする
+impl<'a, T> Unpin for SendFuture<'a, T> {}
+
+impl<'a, T: Send> Future for SendFuture<'a, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let tx = self.tx;
+        let mut state = tx.shared.state.lock().unwrap();
+        let data = self.data.take().expect("polled after completion");
+
+        if state.receivers == 0 {
+            return Poll::Ready(Err(SendError(data)));
+        }
+        if !state.buf.is_full() {
+            tx.enqueue(&mut state, data);
+            return Poll::Ready(Ok(()));
+        }
+
+        // キューが満杯の場合はWakerを登録し、受信されたら起床してもらう
+        if !state.send_wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            state.send_wakers.push(cx.waker().clone());
+        }
+        self.data = Some(data);
+        Poll::Pending
+    }
+}
+
+// recv_asyncがリターンするFuture
+pub struct RecvFuture<'a, T> {
+    rx: &'a Receiver<T>,
+    id: Option<usize>, // 受信待ちに並んでいる場合は識別番号
+}
+
+impl<'a, T> Future for RecvFuture<'a, T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let rx = self.rx;
+        let mut state = rx.shared.state.lock().unwrap();
+
+        let id = match self.id {
+            Some(id) => {
+                // 自身が先頭かつキューが空でなくなるまで待機
+                if (state.is_front(id) && !state.buf.is_empty()) || state.senders == 0 {
+                    self.id = None;
+                    let data = rx.finish_wait(&mut state, id);
+                    return Poll::Ready(data.ok_or(RecvError));
+                }
+                id
+            }
+            None => {
+                // 受信待ちの受信端がいなければ、待機せずに取り出す
+                if state.waiters.is_empty() || state.senders == 0 {
+                    if let Some(data) = rx.dequeue(&mut state) {
+                        return Poll::Ready(Ok(data));
+                    }
+                }
+                if state.senders == 0 {
+                    return Poll::Ready(Err(RecvError));
+                }
+
+                // 受信待ちの末尾に並ぶ
+                let id = state.push_waiter(Waiter::Task(cx.waker().clone()));
+                self.id = Some(id);
+                return Poll::Pending;
+            }
+        };
+
+        // 別のWakerでpollされた場合に備えて更新
+        let waiter = state.waiters.iter_mut().find(|(n, _)| *n == id).unwrap();
+        if !matches!(&waiter.1, Waiter::Task(w) if w.will_wake(cx.waker())) {
+            waiter.1 = Waiter::Task(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+impl<'a, T> Drop for RecvFuture<'a, T> {
+    fn drop(&mut self) {
+        // 待機中にキャンセルされた場合は受信待ちから外す
+        // 先頭だった場合は、次の受信端へ起床を引き継ぐ
+        if let Some(id) = self.id {
+            let mut state = self.rx.shared.state.lock().unwrap();
+            let is_front = state.remove_waiter(id);
+            if is_front && !state.buf.is_empty() {
+                state.wake_receiver();
+                state.notify_selectors();
+            }
+        }
+    }
+}
+
+// 送信端がすべて破棄されるまで受信し続けるイテレータ
+impl<T> Iterator for Receiver<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv().ok()
     }
 }
 
 pub fn channel<T>(max: isize) -> (Sender<T>, Receiver<T>) {
     assert!(max > 0);
-    let sem = Arc::new(Semaphore::new(max));
-    let buf = Arc::new(Mutex::new(LinkedList::new()));
-    let cond = Arc::new(Condvar::new());
+    // キューの領域は最大値分をここで確保する
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            buf: RingBuffer::new(max as usize),
+            senders: 1,
+            receivers: 1,
+            waiters: VecDeque::new(),
+            next_waiter: 0,
+            send_wakers: Vec::new(),
+            selectors: Vec::new(),
+        }),
+        not_full: Condvar::new(),
+    });
     let tx = Sender {
-        sem: sem.clone(),
-        buf: buf.clone(),
-        cond: cond.clone(),
+        shared: shared.clone(),
+    };
+    let rx = Receiver {
+        shared,
+        cond: Arc::new(Condvar::new()),
     };
-    let rx = Receiver { sem, buf, cond };
     (tx, rx)
-}
\ No newline at end of file
+}
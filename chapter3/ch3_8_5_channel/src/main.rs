@@ -1,23 +1,42 @@
 pub mod channel;
-pub mod semaphore;
+pub mod ringbuf;
+pub mod select;
 
-use channel::channel;
+use channel::{
+    channel, RecvError, RecvTimeoutError, SendError, SendTimeoutError, TryRecvError, TrySendError,
+};
+use select::{Select, SelectTimeoutError};
+use std::future::Future;
+use std::sync::mpsc::sync_channel;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::Thread;
+use std::time::{Duration, Instant};
 
 const NUM_LOOP: usize = 5;
 const NUM_THREADS: usize = 2;
 
+// 複数受信時のスレッド数と送信回数
+const NUM_CONSUMERS: usize = 4;
+const NUM_MESSAGES: usize = 10000;
+
+// 性能比較用の送信回数とキューのサイズ
+const NUM_BENCH: usize = 1000000;
+const BENCH_CAP: usize = 1024;
+
 fn main() {
     let (tx, rx) = channel(4);
     let mut v = Vec::new();
 
     // 受信用スレッド
+    // すべての送信端が破棄されるとループを抜ける
     let t = std::thread::spawn(move || {
         let mut cnt = 0;
-        while cnt < NUM_THREADS * NUM_LOOP {
-            let n = rx.recv();
+        for n in rx {
             println!("recv: n = {:?}", n);
             cnt += 1;
         }
+        assert_eq!(cnt, NUM_THREADS * NUM_LOOP);
     });
 
     v.push(t);
@@ -27,13 +46,255 @@ fn main() {
         let tx0 = tx.clone();
         let t = std::thread::spawn(move || {
             for j in 0..NUM_LOOP {
-                tx0.send((i, j));
+                tx0.send((i, j)).unwrap();
             }
         });
         v.push(t);
     }
+    drop(tx);
 
     for t in v {
         t.join().unwrap();
     }
-}
\ No newline at end of file
+
+    timeout();
+    disconnect();
+    mpmc();
+    select();
+    async_channel();
+    bench();
+}
+
+// 相手側が停止していても待機し続けないことを確認
+fn timeout() {
+    let (tx, rx) = channel(1);
+    let dur = Duration::from_millis(100);
+
+    // キューが空
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    assert_eq!(rx.recv_timeout(dur), Err(RecvTimeoutError::Timeout));
+
+    // キューが満杯
+    tx.send(0).unwrap();
+    assert_eq!(tx.try_send(1), Err(TrySendError::Full(1)));
+    assert_eq!(tx.send_timeout(2, dur), Err(SendTimeoutError::Timeout(2)));
+
+    // 受信すると再び送信可能になる
+    assert_eq!(rx.try_recv(), Ok(0));
+    assert_eq!(tx.send_timeout(3, dur), Ok(()));
+    assert_eq!(rx.recv_timeout(dur), Ok(3));
+    println!("timeout: ok");
+}
+
+// 相手側が破棄された場合にエラーとなることを確認
+fn disconnect() {
+    // 送信端の破棄
+    let (tx, rx) = channel(4);
+    let tx0 = tx.clone();
+    tx.send(0).unwrap();
+    drop(tx);
+    drop(tx0);
+    // キューに残っているデータは受信可能
+    assert_eq!(rx.recv(), Ok(0));
+    assert_eq!(rx.recv(), Err(RecvError));
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+
+    // 受信端の破棄
+    let (tx, rx) = channel(1);
+    tx.send(0).unwrap();
+    let tx0 = tx.clone();
+    // キューが満杯で待機中の送信側も起床される
+    let t = std::thread::spawn(move || tx0.send(1));
+    std::thread::sleep(Duration::from_millis(100));
+    drop(rx);
+    assert_eq!(t.join().unwrap(), Err(SendError(1)));
+    assert_eq!(tx.send(2), Err(SendError(2)));
+    assert_eq!(tx.try_send(3), Err(TrySendError::Disconnected(3)));
+    println!("disconnect: ok");
+}
+
+// 複数の受信端から受信し、すべてのデータがちょうど1回ずつ受信されることを確認
+fn mpmc() {
+    let (tx, rx) = channel(4);
+    let mut consumers = Vec::new();
+
+    // 受信用スレッド
+    for _ in 0..NUM_CONSUMERS {
+        let rx0 = rx.clone();
+        let t = std::thread::spawn(move || rx0.collect::<Vec<_>>());
+        consumers.push(t);
+    }
+    drop(rx);
+
+    // 送信用スレッド
+    let mut producers = Vec::new();
+    for i in 0..NUM_THREADS {
+        let tx0 = tx.clone();
+        let t = std::thread::spawn(move || {
+            for j in 0..NUM_MESSAGES {
+                tx0.send(i * NUM_MESSAGES + j).unwrap();
+            }
+        });
+        producers.push(t);
+    }
+    drop(tx);
+
+    for t in producers {
+        t.join().unwrap();
+    }
+
+    let mut received = Vec::new();
+    for t in consumers {
+        let mut r = t.join().unwrap();
+        print!("{} ", r.len());
+        received.append(&mut r);
+    }
+    println!();
+
+    received.sort_unstable();
+    assert_eq!(received.len(), NUM_THREADS * NUM_MESSAGES);
+    for (i, n) in received.iter().enumerate() {
+        assert_eq!(i, *n);
+    }
+    println!("mpmc: ok");
+}
+
+// コマンド用とシャットダウン用のチャネルを同時に待機
+fn select() {
+    let (cmd_tx, cmd_rx) = channel(4);
+    let (shutdown_tx, shutdown_rx) = channel::<()>(1);
+
+    let t = std::thread::spawn(move || {
+        let mut cmds = Vec::new();
+        let mut sel = Select::new();
+        let cmd = sel.recv(&cmd_rx);
+        let shutdown = sel.recv(&shutdown_rx);
+        loop {
+            match sel.select() {
+                i if i == cmd => {
+                    if let Ok(n) = cmd_rx.try_recv() {
+                        cmds.push(n);
+                    }
+                }
+                i if i == shutdown => break,
+                _ => unreachable!(),
+            }
+        }
+        cmds
+    });
+
+    for i in 0..NUM_LOOP {
+        cmd_tx.send(i).unwrap();
+    }
+    // キューが空くまで待機して送信
+    let mut sel = Select::new();
+    sel.send(&cmd_tx);
+    while cmd_tx.try_send(NUM_LOOP).is_err() {
+        sel.select();
+    }
+    // 複数が操作可能な場合は登録順で先のものが選ばれるため、
+    // 送信済みのコマンドはすべて処理されてからシャットダウンする
+    shutdown_tx.send(()).unwrap();
+    assert_eq!(t.join().unwrap(), (0..=NUM_LOOP).collect::<Vec<_>>());
+
+    // いずれも操作可能にならない場合はタイムアウト
+    let (_tx, rx) = channel::<()>(1);
+    let (tx, _rx) = channel(1);
+    tx.send(()).unwrap();
+    let mut sel = Select::new();
+    sel.recv(&rx);
+    sel.send(&tx);
+    assert_eq!(
+        sel.select_timeout(Duration::from_millis(100)),
+        Err(SelectTimeoutError)
+    );
+    println!("select: ok");
+}
+
+// 起床時にスレッドをunparkするWaker
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+// Futureが完了するまで現在のスレッドで実行
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+    let mut ctx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(v) = future.as_mut().poll(&mut ctx) {
+            return v;
+        }
+        std::thread::park();
+    }
+}
+
+// 非同期タスクとスレッドの間で、同じチャネルを用いて送受信
+fn async_channel() {
+    let (tx, rx) = channel(2);
+    let (result_tx, result_rx) = channel(2);
+
+    // 非同期に受信して、2倍した値をスレッドへ送信
+    let rx0 = rx.clone();
+    let t = std::thread::spawn(move || {
+        block_on(async move {
+            while let Ok(n) = rx0.recv_async().await {
+                result_tx.send_async(n * 2).await.unwrap();
+            }
+        })
+    });
+    drop(rx);
+
+    // スレッドから送信
+    let sender = std::thread::spawn(move || {
+        for i in 0..NUM_MESSAGES {
+            tx.send(i).unwrap();
+        }
+    });
+
+    let received: Vec<_> = result_rx.collect();
+    sender.join().unwrap();
+    t.join().unwrap();
+    assert_eq!(received, (0..NUM_MESSAGES).map(|n| n * 2).collect::<Vec<_>>());
+
+    // 待機中にキャンセルされたFutureが、後続の受信を妨げない
+    let (tx, rx) = channel(1);
+    let rx0 = rx.clone();
+    {
+        let mut future = std::pin::pin!(rx.recv_async());
+        let mut ctx = Context::from_waker(Waker::noop());
+        assert!(future.as_mut().poll(&mut ctx).is_pending());
+    }
+    tx.send(1).unwrap();
+    assert_eq!(rx0.recv_timeout(Duration::from_millis(100)), Ok(1));
+    println!("async: ok");
+}
+
+// std::sync::mpsc::sync_channelとのスループット比較
+fn bench() {
+    let (tx, rx) = channel(BENCH_CAP as isize);
+    let start = Instant::now();
+    let t = std::thread::spawn(move || {
+        for i in 0..NUM_BENCH {
+            tx.send(i).unwrap();
+        }
+    });
+    assert_eq!(rx.count(), NUM_BENCH);
+    t.join().unwrap();
+    println!("channel:      {:?}", start.elapsed());
+
+    let (tx, rx) = sync_channel(BENCH_CAP);
+    let start = Instant::now();
+    let t = std::thread::spawn(move || {
+        for i in 0..NUM_BENCH {
+            tx.send(i).unwrap();
+        }
+    });
+    assert_eq!(rx.iter().count(), NUM_BENCH);
+    t.join().unwrap();
+    println!("sync_channel: {:?}", start.elapsed());
+}
@@ -0,0 +1,48 @@
+// 固定長のリングバッファ
+// 生成時に容量分の領域を確保し、以降の追加、取り出しでは確保しない
+pub struct RingBuffer<T> {
+    buf: Box<[Option<T>]>,
+    head: usize, // 先頭要素のインデックス
+    len: usize,  // 要素数
+}
+
+impl<T> RingBuffer<T> {
+    pub fn new(cap: usize) -> Self {
+        assert!(cap > 0);
+        RingBuffer {
+            buf: (0..cap).map(|_| None).collect(),
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == self.buf.len()
+    }
+
+    // 末尾に追加。満杯の場合は追加できなかった値を返す
+    pub fn push_back(&mut self, v: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(v);
+        }
+        let tail = (self.head + self.len) % self.buf.len();
+        self.buf[tail] = Some(v);
+        self.len += 1;
+        Ok(())
+    }
+
+    // 先頭から取り出し
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let v = self.buf[self.head].take();
+        self.head = (self.head + 1) % self.buf.len();
+        self.len -= 1;
+        v
+    }
+}
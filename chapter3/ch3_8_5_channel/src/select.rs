@@ -0,0 +1,126 @@
+use crate::channel::{Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+// select_timeoutのエラー
+#[derive(Debug, PartialEq, Eq)]
+pub struct SelectTimeoutError;
+
+// チャネルの状態が変化したことをSelectへ通知するための型
+pub struct Signal {
+    notified: Mutex<bool>,
+    cond: Condvar,
+}
+
+impl Signal {
+    fn new() -> Self {
+        Signal {
+            notified: Mutex::new(false),
+            cond: Condvar::new(),
+        }
+    }
+
+    pub fn notify(&self) {
+        let mut notified = self.notified.lock().unwrap();
+        *notified = true;
+        self.cond.notify_one();
+    }
+
+    // 通知されるまで待機。deadlineを過ぎた場合はfalseをリターン
+    fn wait(&self, deadline: Option<Instant>) -> bool {
+        let mut notified = self.notified.lock().unwrap();
+        while !*notified {
+            match deadline {
+                None => notified = self.cond.wait(notified).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return false;
+                    }
+                    notified = self.cond.wait_timeout(notified, deadline - now).unwrap().0;
+                }
+            }
+        }
+        *notified = false;
+        true
+    }
+}
+
+// Selectで待機可能な操作
+pub trait Operation {
+    // 待機せずに操作可能か
+    fn is_ready(&self) -> bool;
+    // 状態の変化を通知するSignalを登録
+    fn watch(&self, signal: &Arc<Signal>);
+    // 登録したSignalを削除
+    fn unwatch(&self, signal: &Arc<Signal>);
+}
+
+// 複数の送信端、受信端のいずれかが操作可能になるまで待機するための型
+pub struct Select<'a> {
+    ops: Vec<&'a dyn Operation>,
+}
+
+impl<'a> Select<'a> {
+    pub fn new() -> Self {
+        Select { ops: Vec::new() }
+    }
+
+    // 受信を登録し、その番号をリターン
+    // キューが空でないか、すべての送信端が破棄されると操作可能となる
+    pub fn recv<T>(&mut self, rx: &'a Receiver<T>) -> usize {
+        self.ops.push(rx);
+        self.ops.len() - 1
+    }
+
+    // 送信を登録し、その番号をリターン
+    // キューが満杯でないか、すべての受信端が破棄されると操作可能となる
+    pub fn send<T>(&mut self, tx: &'a Sender<T>) -> usize {
+        self.ops.push(tx);
+        self.ops.len() - 1
+    }
+
+    // いずれかが操作可能になるまで待機し、その番号をリターン
+    // 複数が操作可能な場合は、登録順で最初のものをリターン
+    //
+    // 他のスレッドが先に操作する可能性があるため、
+    // 実際の操作にはtry_recv、try_sendを用い、失敗した場合は再度selectすること
+    pub fn select(&self) -> usize {
+        self.select_deadline(None).unwrap()
+    }
+
+    // 最大でdurだけ待機するselect。タイムアウトした場合はエラー
+    pub fn select_timeout(&self, dur: Duration) -> Result<usize, SelectTimeoutError> {
+        self.select_deadline(Some(Instant::now() + dur))
+    }
+
+    fn select_deadline(&self, deadline: Option<Instant>) -> Result<usize, SelectTimeoutError> {
+        assert!(!self.ops.is_empty());
+
+        // 状態を検査する前に登録し、検査後の変化を取りこぼさないようにする
+        let signal = Arc::new(Signal::new());
+        for op in self.ops.iter() {
+            op.watch(&signal);
+        }
+
+        let result = loop {
+            if let Some(i) = self.ops.iter().position(|op| op.is_ready()) {
+                break Ok(i);
+            }
+            if !signal.wait(deadline) {
+                break Err(SelectTimeoutError);
+            }
+        };
+
+        for op in self.ops.iter() {
+            op.unwatch(&signal);
+        }
+        result
+    }
+}
+
+impl<'a> Default for Select<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
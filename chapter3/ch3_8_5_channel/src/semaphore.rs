@@ -1,33 +0,0 @@
-use std::sync::{Condvar, Mutex};
-
-pub struct Semaphore {
-    mutex: Mutex<isize>,
-    cond: Condvar,
-    max: isize,
-}
-
-impl Semaphore {
-    pub fn new(max: isize) -> Self {
-        Semaphore {
-            mutex: Mutex::new(0),
-            cond: Condvar::new(),
-            max,
-        }
-    }
-
-    pub fn wait(&self) {
-        let mut cnt = self.mutex.lock().unwrap();
-        while *cnt >= self.max {
-            cnt = self.cond.wait(cnt).unwrap();
-        }
-        *cnt += 1;
-    }
-
-    pub fn post(&self) {
-        let mut cnt = self.mutex.lock().unwrap();
-        *cnt -= 1;
-        if *cnt <= self.max {
-            self.cond.notify_one();
-        }
-    }
-}
@@ -0,0 +1,158 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll, Waker};
+
+// カウンタの状態
+struct State {
+    available: usize,                  // 獲得可能なカウンタの数
+    waiters: VecDeque<(usize, Waker)>, // 獲得待ちのタスク。到着順に並ぶ
+    granted: Vec<usize>,               // カウンタを譲渡済みだが、まだpollされていないタスク
+    next_id: usize,                    // 獲得待ちの識別番号
+}
+
+impl State {
+    // 獲得可能なカウンタを、獲得待ちの先頭から順に譲渡して起床
+    fn grant(&mut self) {
+        while self.available > 0 {
+            let Some((id, waker)) = self.waiters.pop_front() else {
+                break;
+            };
+            self.available -= 1;
+            self.granted.push(id);
+            waker.wake();
+        }
+    }
+
+    // 譲渡済みなら取り除いてtrueをリターン
+    fn take_granted(&mut self, id: usize) -> bool {
+        match self.granted.iter().position(|n| *n == id) {
+            Some(pos) => {
+                self.granted.swap_remove(pos);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// スレッドをブロックせず、Wakerで起床するセマフォ
+pub struct AsyncSemaphore {
+    state: Mutex<State>,
+}
+
+// 獲得したカウンタを、スコープを外れた際に自動で戻すための型
+pub struct AsyncSemaphorePermit<'a> {
+    sem: &'a AsyncSemaphore,
+}
+
+impl AsyncSemaphore {
+    // 獲得可能なカウンタがpermits個のセマフォを生成
+    pub fn new(permits: usize) -> Self {
+        AsyncSemaphore {
+            state: Mutex::new(State {
+                available: permits,
+                waiters: VecDeque::new(),
+                granted: Vec::new(),
+                next_id: 0,
+            }),
+        }
+    }
+
+    // カウンタを獲得するFutureをリターン
+    // 獲得待ちのタスクは到着順にカウンタを獲得する
+    pub fn acquire(&self) -> Acquire<'_> {
+        Acquire { sem: self, id: None }
+    }
+
+    // 待機せずにカウンタの獲得を試みる
+    // 獲得待ちのタスクがいる場合は、そちらを優先してNoneをリターン
+    pub fn try_acquire(&self) -> Option<AsyncSemaphorePermit<'_>> {
+        let mut state = self.state.lock().unwrap();
+        if state.available == 0 || !state.waiters.is_empty() {
+            return None;
+        }
+        state.available -= 1;
+        Some(AsyncSemaphorePermit { sem: self })
+    }
+
+    // 獲得可能なカウンタの数
+    pub fn available_permits(&self) -> usize {
+        self.state.lock().unwrap().available
+    }
+
+    fn release(&self, state: &mut State) {
+        state.available += 1;
+        state.grant();
+    }
+}
+
+impl<'a> Drop for AsyncSemaphorePermit<'a> {
+    fn drop(&mut self) {
+        let mut state = self.sem.state.lock().unwrap();
+        self.sem.release(&mut state);
+    }
+}
+
+// acquireがリターンするFuture
+pub struct Acquire<'a> {
+    sem: &'a AsyncSemaphore,
+    id: Option<usize>, // 獲得待ちに並んでいる場合は識別番号
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = AsyncSemaphorePermit<'a>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let sem = self.sem;
+        let mut state = sem.state.lock().unwrap();
+
+        match self.id {
+            None => {
+                // 獲得待ちのタスクがいなければ、待機せずに獲得
+                if state.available > 0 && state.waiters.is_empty() {
+                    state.available -= 1;
+                    return Poll::Ready(AsyncSemaphorePermit { sem });
+                }
+
+                // 獲得待ちの末尾に並ぶ
+                let id = state.next_id;
+                state.next_id = state.next_id.wrapping_add(1);
+                state.waiters.push_back((id, cx.waker().clone()));
+                self.id = Some(id);
+                Poll::Pending
+            }
+            Some(id) => {
+                // 解放したタスクから譲渡された
+                if state.take_granted(id) {
+                    self.id = None;
+                    return Poll::Ready(AsyncSemaphorePermit { sem });
+                }
+
+                // 別のWakerでpollされた場合に備えて更新
+                let waiter = state.waiters.iter_mut().find(|(n, _)| *n == id).unwrap();
+                if !waiter.1.will_wake(cx.waker()) {
+                    waiter.1 = cx.waker().clone();
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<'a> Drop for Acquire<'a> {
+    fn drop(&mut self) {
+        // 獲得待ちの途中でキャンセルされた場合
+        if let Some(id) = self.id {
+            let mut state = self.sem.state.lock().unwrap();
+            if state.take_granted(id) {
+                // 譲渡済みのカウンタは、次の獲得待ちのタスクへ譲渡
+                self.sem.release(&mut state);
+            } else {
+                // 獲得待ちから外す
+                state.waiters.retain(|(n, _)| *n != id);
+            }
+        }
+    }
+}
@@ -1,14 +1,24 @@
+mod async_semaphore;
 mod semaphore;
 
-use semaphore::Semaphore;
+use async_semaphore::AsyncSemaphore;
+use futures::executor::LocalPool;
+use futures::task::LocalSpawnExt;
+use semaphore::{ReleaseError, Semaphore};
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
 
 const NUM_LOOP: usize = 5;
 const NUM_THREADS: usize = 2;
-const SEM_NUM: isize = 1;
+const SEM_NUM: usize = 1;
 
-static mut CNT: AtomicUsize = AtomicUsize::new(0);
+static CNT: AtomicUsize = AtomicUsize::new(0);
 
 fn main() {
     let mut v = Vec::new();
@@ -19,18 +29,17 @@ fn main() {
         let t = std::thread::spawn(move || {
             // スレッド毎にNUM_LOOP回ループする
             for _ in 0..NUM_LOOP {
-                // セマフォ内のカウンタをインクリメント
-                // もし、カウンタがSEM_NUM以上であれば、カウンタがSEM_NUM未満になるまで待機
-                s.wait();
+                // セマフォ内の獲得可能なカウンタをデクリメント
+                // もし、獲得可能なカウンタが0であれば、解放されるまで待機
+                // _permitがスコープを外れると、獲得可能なカウンタをインクリメント
+                let _permit = s.acquire();
                 // `fetch_add`で古い値を読み、それに加算した値を書き込み、古い値を返す
-                unsafe { CNT.fetch_add(1, Ordering::SeqCst) };
-                let n = unsafe { CNT.load(Ordering::SeqCst) };
+                CNT.fetch_add(1, Ordering::SeqCst);
+                let n = CNT.load(Ordering::SeqCst);
                 println!("{:?}, semaphore: i = {}, CNT = {}", std::thread::current().id(), i, n);
-                assert!((n as isize) <= SEM_NUM);
+                assert!(n <= SEM_NUM);
                 // `fetch_sub`で古い値を読み、それを減算した値を書き込み、古い値を返す
-                unsafe { CNT.fetch_sub(1, Ordering::SeqCst) };
-                // セマフォ内のカウンタをデクリメント
-                s.post();
+                CNT.fetch_sub(1, Ordering::SeqCst);
             }
         });
         v.push(t)
@@ -39,4 +48,163 @@ fn main() {
     for t in v {
         t.join().unwrap();
     }
+
+    permit();
+    resize();
+    async_semaphore();
+    cancel();
+}
+
+// パニックしてもカウンタが戻されることと、各獲得関数の動作を確認
+fn permit() {
+    let sem = Arc::new(Semaphore::new(2));
+
+    // 獲得中にパニックしたスレッド
+    let s = sem.clone();
+    let t = std::thread::spawn(move || {
+        let _permit = s.acquire_many(2);
+        panic!("panic while holding permits");
+    });
+    assert!(t.join().is_err());
+
+    // パニックしたスレッドのカウンタは解放済み
+    let p0 = sem.try_acquire().unwrap();
+    let p1 = sem.acquire_timeout(Duration::from_millis(100)).unwrap();
+
+    // 最大値に達している
+    assert!(sem.try_acquire().is_none());
+    assert!(sem.acquire_timeout(Duration::from_millis(100)).is_none());
+
+    drop(p0);
+    drop(p1);
+    let _p = sem.acquire_many(2);
+    println!("permit: ok");
+}
+
+// 総数を超える解放の検出と、実行中のカウンタ数の変更を確認
+fn resize() {
+    let sem = Arc::new(Semaphore::new(1));
+
+    // 獲得していないカウンタは解放できない
+    assert_eq!(sem.release_many(1), Err(ReleaseError));
+    let p = sem.acquire();
+    p.forget();
+    assert_eq!(sem.available_permits(), 0);
+    assert_eq!(sem.release_many(1), Ok(()));
+    assert_eq!(sem.available_permits(), 1);
+    // forgetした数を超えては解放できない
+    assert_eq!(sem.release_many(1), Err(ReleaseError));
+
+    // 総数を超える獲得は、カウンタが追加されるまで待機
+    let s = sem.clone();
+    let t = std::thread::spawn(move || {
+        let _p = s.acquire_many(3);
+    });
+    std::thread::sleep(Duration::from_millis(100));
+    sem.add_permits(2);
+    t.join().unwrap();
+    assert_eq!(sem.available_permits(), 3);
+
+    // 獲得中のカウンタは削除されない
+    let p = sem.acquire();
+    assert_eq!(sem.forget_permits(5), 2);
+    assert!(sem.try_acquire().is_none());
+    drop(p);
+    assert_eq!(sem.available_permits(), 1);
+    assert_eq!(sem.release_many(1), Err(ReleaseError));
+
+    // 他のカウンタを獲得中でも、forgetした数を超える解放を検出
+    let sem = Semaphore::new(2);
+    let held = sem.acquire();
+    sem.acquire().forget();
+    assert_eq!(sem.release_many(2), Err(ReleaseError));
+    assert_eq!(sem.release_many(1), Ok(()));
+    drop(held);
+    assert_eq!(sem.available_permits(), 2);
+    println!("resize: ok");
+}
+
+// 一度だけPendingをリターンし、他のタスクへ実行を譲るFuture
+struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+// 1スレッドで実行される複数のタスクが、スレッドをブロックせずに獲得待ちすることを確認
+fn async_semaphore() {
+    const NUM_TASKS: usize = 8;
+
+    let mut pool = LocalPool::new();
+    let spawner = pool.spawner();
+    let sem = Rc::new(AsyncSemaphore::new(SEM_NUM + 1));
+    let cnt = Rc::new(Cell::new(0)); // 獲得中のタスク数
+    let done = Rc::new(Cell::new(0)); // 終了したタスク数
+
+    for i in 0..NUM_TASKS {
+        let (sem, cnt, done) = (sem.clone(), cnt.clone(), done.clone());
+        spawner
+            .spawn_local(async move {
+                for _ in 0..NUM_LOOP {
+                    let _permit = sem.acquire().await;
+                    cnt.set(cnt.get() + 1);
+                    println!("async semaphore: i = {}, CNT = {}", i, cnt.get());
+                    assert!(cnt.get() <= SEM_NUM + 1);
+                    // カウンタを保持したまま他のタスクへ実行を譲る
+                    YieldNow { yielded: false }.await;
+                    cnt.set(cnt.get() - 1);
+                }
+                done.set(done.get() + 1);
+            })
+            .unwrap();
+    }
+
+    pool.run();
+    assert_eq!(done.get(), NUM_TASKS);
+    assert_eq!(sem.available_permits(), SEM_NUM + 1);
+    println!("async semaphore: ok");
+}
+
+// 獲得待ちの順序と、獲得待ちのFutureをドロップした場合の動作を確認
+fn cancel() {
+    let sem = AsyncSemaphore::new(1);
+    let mut cx = Context::from_waker(Waker::noop());
+
+    let p = sem.try_acquire().unwrap();
+    let mut a = Box::pin(sem.acquire());
+    let mut b = Box::pin(sem.acquire());
+    let mut c = Box::pin(sem.acquire());
+    assert!(a.as_mut().poll(&mut cx).is_pending());
+    assert!(b.as_mut().poll(&mut cx).is_pending());
+    assert!(c.as_mut().poll(&mut cx).is_pending());
+
+    // 獲得待ちのタスクがいる間は、try_acquireで追い越せない
+    drop(p);
+    assert!(sem.try_acquire().is_none());
+
+    // 獲得待ちの途中でキャンセル
+    drop(b);
+
+    // 譲渡されたカウンタは、キャンセル時に次の獲得待ちへ渡される
+    drop(a);
+    let p = match c.as_mut().poll(&mut cx) {
+        Poll::Ready(p) => p,
+        Poll::Pending => panic!("permit was not handed over"),
+    };
+    assert_eq!(sem.available_permits(), 0);
+    drop(p);
+    drop(c);
+    assert_eq!(sem.available_permits(), 1);
+    println!("cancel: ok");
 }
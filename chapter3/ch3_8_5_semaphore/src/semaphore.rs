@@ -1,33 +1,131 @@
 use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+// release_manyのエラー。forgetしたカウンタの数を超えて解放する場合
+#[derive(Debug, PartialEq, Eq)]
+pub struct ReleaseError;
+
+// カウンタの状態
+struct Permits {
+    available: usize, // 獲得可能なカウンタの数
+    forgotten: usize, // forgetされ、release_manyで解放可能なカウンタの数
+}
 
 pub struct Semaphore {
-    mutex: Mutex<isize>,
+    mutex: Mutex<Permits>,
     cond: Condvar,
-    max: isize,
+}
+
+// 獲得したカウンタを、スコープを外れた際に自動で戻すための型
+pub struct SemaphorePermit<'a> {
+    sem: &'a Semaphore,
+    n: usize, // 獲得したカウンタの数
 }
 
 impl Semaphore {
-    pub fn new(max: isize) -> Self {
+    // 獲得可能なカウンタがpermits個のセマフォを生成
+    pub fn new(permits: usize) -> Self {
         Semaphore {
-            mutex: Mutex::new(0),
+            mutex: Mutex::new(Permits {
+                available: permits,
+                forgotten: 0,
+            }),
             cond: Condvar::new(),
-            max,
         }
     }
 
-    pub fn wait(&self) {
-        let mut cnt = self.mutex.lock().unwrap();
-        while *cnt >= self.max {
-            cnt = self.cond.wait(cnt).unwrap();
+    // カウンタを獲得し、ドロップ時に解放するSemaphorePermitをリターン
+    pub fn acquire(&self) -> SemaphorePermit<'_> {
+        self.acquire_many(1)
+    }
+
+    // 待機せずにカウンタの獲得を試みる
+    // 獲得可能なカウンタがない場合はNoneをリターン
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        let mut p = self.mutex.lock().unwrap();
+        if p.available == 0 {
+            return None;
         }
-        *cnt += 1;
+        p.available -= 1;
+        Some(SemaphorePermit { sem: self, n: 1 })
     }
 
-    pub fn post(&self) {
-        let mut cnt = self.mutex.lock().unwrap();
-        *cnt -= 1;
-        if *cnt <= self.max {
-            self.cond.notify_one();
+    // 最大でdurだけ待機してカウンタを獲得
+    // タイムアウトした場合はNoneをリターン
+    pub fn acquire_timeout(&self, dur: Duration) -> Option<SemaphorePermit<'_>> {
+        let p = self.mutex.lock().unwrap();
+        let (mut p, result) = self
+            .cond
+            .wait_timeout_while(p, dur, |p| p.available == 0)
+            .unwrap();
+        if result.timed_out() {
+            return None;
         }
+        p.available -= 1;
+        Some(SemaphorePermit { sem: self, n: 1 })
+    }
+
+    // n個のカウンタをまとめて獲得
+    // 総数がn未満の場合は、add_permitsで追加されるまで待機
+    pub fn acquire_many(&self, n: usize) -> SemaphorePermit<'_> {
+        let p = self.mutex.lock().unwrap();
+        let mut p = self.cond.wait_while(p, |p| p.available < n).unwrap();
+        p.available -= n;
+        SemaphorePermit { sem: self, n }
+    }
+
+    // SemaphorePermit::forgetしたn個のカウンタをまとめて解放
+    // forgetしたカウンタの数を超えて解放しようとした場合はエラー
+    // 他に獲得中のカウンタがあっても、総数を超える解放を検出できる
+    pub fn release_many(&self, n: usize) -> Result<(), ReleaseError> {
+        let mut p = self.mutex.lock().unwrap();
+        if n > p.forgotten {
+            return Err(ReleaseError);
+        }
+        p.forgotten -= n;
+        self.release(&mut p, n);
+        Ok(())
+    }
+
+    // カウンタをn個追加
+    pub fn add_permits(&self, n: usize) {
+        let mut p = self.mutex.lock().unwrap();
+        self.release(&mut p, n);
+    }
+
+    // 獲得可能なカウンタのうち、最大n個を削除し、削除した数をリターン
+    // 獲得中のカウンタや、forgetしたカウンタは削除されない
+    pub fn forget_permits(&self, n: usize) -> usize {
+        let mut p = self.mutex.lock().unwrap();
+        let n = n.min(p.available);
+        p.available -= n;
+        n
+    }
+
+    // 獲得可能なカウンタの数
+    pub fn available_permits(&self) -> usize {
+        self.mutex.lock().unwrap().available
+    }
+
+    fn release(&self, p: &mut Permits, n: usize) {
+        p.available += n;
+        // 待機中のスレッドが必要とするカウンタの数は異なるため、すべて起床
+        self.cond.notify_all();
+    }
+}
+
+impl<'a> SemaphorePermit<'a> {
+    // カウンタを解放せずに破棄
+    // 後でrelease_manyにより解放する
+    pub fn forget(self) {
+        self.sem.mutex.lock().unwrap().forgotten += self.n;
+        std::mem::forget(self);
+    }
+}
+
+impl<'a> Drop for SemaphorePermit<'a> {
+    fn drop(&mut self) {
+        let mut p = self.sem.mutex.lock().unwrap();
+        self.sem.release(&mut p, self.n);
     }
 }
@@ -0,0 +1,153 @@
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+// メモリバリア用
+use std::sync::atomic::{fence, AtomicBool, AtomicU64, Ordering};
+
+// 最大N個のスレッドで共有できるパン屋のアルゴリズムによるロック
+pub struct BakeryLock<T, const N: usize> {
+    registered: [AtomicBool; N], // スロットが使用中か
+    entering: [AtomicBool; N],   // チケット取得中か
+    tickets: [AtomicU64; N],     // チケット番号。0の場合は処理中でない
+    data: UnsafeCell<T>,
+}
+
+// ロックのスロットを保持する型
+// スコープを外れるとスロットを解放する
+pub struct BakeryHandle<'a, T, const N: usize> {
+    lock: &'a BakeryLock<T, N>,
+    idx: usize, // スロット番号
+}
+
+// ロック解放および、保護対象データへのアクセスを行うための型
+pub struct BakeryLockGuard<'a, T, const N: usize> {
+    lock: &'a BakeryLock<T, N>,
+    idx: usize,
+    // ガードは&mut Tとして振る舞うため、共有にはT: Syncを要求する
+    _marker: PhantomData<&'a mut T>,
+}
+
+impl<T, const N: usize> BakeryLock<T, N> {
+    pub fn new(v: T) -> Self {
+        BakeryLock {
+            registered: std::array::from_fn(|_| AtomicBool::new(false)),
+            entering: std::array::from_fn(|_| AtomicBool::new(false)),
+            tickets: std::array::from_fn(|_| AtomicU64::new(0)),
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    // 空いているスロットを確保してハンドルをリターン
+    // N個のスロットがすべて使用中の場合はNone
+    pub fn register(&self) -> Option<BakeryHandle<'_, T, N>> {
+        for idx in 0..N {
+            if self.registered[idx]
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                return Some(BakeryHandle { lock: self, idx });
+            }
+        }
+        None
+    }
+
+    // ロック関数。idxはスロット番号
+    fn lock(&self, idx: usize) -> BakeryLockGuard<'_, T, N> {
+        // ここからチケット取得処理
+        fence(Ordering::SeqCst);
+        self.entering[idx].store(true, Ordering::SeqCst);
+        fence(Ordering::SeqCst);
+
+        // 現在配布されているチケットの最大値を取得
+        let mut max = 0;
+        for t in self.tickets.iter() {
+            max = max.max(t.load(Ordering::SeqCst));
+        }
+        // 最大値+1を自分のチケット番号とする
+        let ticket = max + 1;
+        self.tickets[idx].store(ticket, Ordering::SeqCst);
+
+        fence(Ordering::SeqCst);
+        self.entering[idx].store(false, Ordering::SeqCst);
+        fence(Ordering::SeqCst);
+
+        // ここから待機処理
+        for i in 0..N {
+            if i == idx {
+                continue;
+            }
+
+            // スレッドiがチケット取得中なら待機
+            while self.entering[i].load(Ordering::SeqCst) {
+                std::hint::spin_loop();
+            }
+
+            loop {
+                // スレッドiのチケット番号より
+                // 自分の番号の方が若いか、
+                // チケット番号が同じでかつ、
+                // 自分の方がスロット番号が若いか、
+                // スレッドiが処理中でない場合に待機終了
+                let t = self.tickets[i].load(Ordering::SeqCst);
+                if t == 0 || ticket < t || (ticket == t && idx < i) {
+                    break;
+                }
+                std::hint::spin_loop();
+            }
+        }
+        fence(Ordering::SeqCst);
+        BakeryLockGuard {
+            lock: self,
+            idx,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T, const N: usize> BakeryHandle<'a, T, N> {
+    // ロックを獲得
+    // ガードがハンドルを借用するため、同じスロットで二重にロックすることはない
+    pub fn lock(&mut self) -> BakeryLockGuard<'_, T, N> {
+        self.lock.lock(self.idx)
+    }
+
+    // 確保したスロット番号
+    pub fn index(&self) -> usize {
+        self.idx
+    }
+}
+
+impl<'a, T, const N: usize> Drop for BakeryHandle<'a, T, N> {
+    // スロットを解放
+    fn drop(&mut self) {
+        self.lock.registered[self.idx].store(false, Ordering::Release);
+    }
+}
+
+// BakeryLock型はスレッド間で共有可能と指定
+unsafe impl<T: Send, const N: usize> Sync for BakeryLock<T, N> {}
+unsafe impl<T: Send, const N: usize> Send for BakeryLock<T, N> {}
+
+impl<'a, T, const N: usize> Drop for BakeryLockGuard<'a, T, N> {
+    // ロック解放処理
+    fn drop(&mut self) {
+        fence(Ordering::SeqCst);
+        self.lock.tickets[self.idx].store(0, Ordering::SeqCst);
+    }
+}
+
+// 保護対象データのimmutableな参照外し
+impl<'a, T, const N: usize> Deref for BakeryLockGuard<'a, T, N> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+// 保護対象データのmutableな参照外し
+impl<'a, T, const N: usize> DerefMut for BakeryLockGuard<'a, T, N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
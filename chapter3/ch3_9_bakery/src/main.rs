@@ -1,120 +1,97 @@
-// 最適化抑制読み書き用
-use std::ptr::{read_volatile, write_volatile};
-// メモリバリア用
-use std::sync::atomic::{fence, Ordering};
+mod bakery;
+
+use bakery::BakeryLock;
+use ch4_7_barrier::SpinLock;
+use ch7_1_2_ticketlock::TicketLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::thread;
+use std::time::Instant;
 
 const NUM_THREADS: usize = 4;
 const NUM_LOOP: usize = 100000;
 
-macro_rules! read_mem {
-    ($addr: expr) => {
-        unsafe { read_volatile($addr) }
-    };
+// 比較対象のロック
+trait Counter: Send + Sync + 'static {
+    // NUM_LOOPだけロックを獲得してインクリメント
+    // ロック中にcsを呼び出す
+    fn run(&self, cs: &dyn Fn());
+    fn count(&self) -> u64;
 }
 
-macro_rules! write_mem {
-    ($addr: expr, $val: expr) => {
-        unsafe { write_volatile($addr, $val) }
-    };
-}
+impl Counter for BakeryLock<u64, NUM_THREADS> {
+    fn run(&self, cs: &dyn Fn()) {
+        // 空いているスロットを確保
+        let mut handle = self.register().unwrap();
+        for _ in 0..NUM_LOOP {
+            let mut count = handle.lock();
+            cs();
+            *count += 1;
+        }
+    }
 
-struct BakeryLock {
-    entering: [bool; NUM_THREADS],
-    tickets: [Option<u64>; NUM_THREADS],
+    fn count(&self) -> u64 {
+        *self.register().unwrap().lock()
+    }
 }
 
-impl BakeryLock {
-    // ロック関数。idxはスレッド番号
-    fn lock(&mut self, idx: usize) -> LockGuard {
-        // ここからチケット取得処理 <5>
-        fence(Ordering::SeqCst);
-        write_mem!(&mut self.entering[idx], true);
-        fence(Ordering::SeqCst);
-
-        // 現在配布されているチケットの最大値を取得 <6>
-        let mut max = 0;
-        for i in 0..NUM_THREADS {
-            if let Some(t) = read_mem!(&self.tickets[i]) {
-                max = max.max(t);
-            }
+impl Counter for SpinLock<u64> {
+    fn run(&self, cs: &dyn Fn()) {
+        for _ in 0..NUM_LOOP {
+            let mut count = self.lock();
+            cs();
+            *count += 1;
         }
-        // 最大値+1を自分のチケット番号とする <7>
-        let ticket = max + 1;
-        write_mem!(&mut self.tickets[idx], Some(ticket));
-
-        fence(Ordering::SeqCst);
-        write_mem!(&mut self.entering[idx], false); // <8>
-        fence(Ordering::SeqCst);
-
-        // ここから待機処理 <9>
-        for i in 0..NUM_THREADS {
-            if i == idx {
-                continue;
-            }
+    }
 
-            // スレッドiがチケット取得中なら待機
-            while read_mem!(&self.entering[i]) {} // <10>
-
-            loop {
-                // スレッドiと自分の優先順位を比較して
-                // 自分の方が優先順位が高いか、
-                // スレッドiが処理中でない場合に待機を終了 <11>
-                match read_mem!(&self.tickets[i]) {
-                    Some(t) => {
-                        // スレッドiのチケット番号より
-                        // 自分の番号の方が若いか、
-                        // チケット番号が同じでかつ、
-                        // 自分の方がスレッド番号が若い場合に
-                        // 待機終了
-                        if ticket < t ||
-                           (ticket == t && idx < i) {
-                            break;
-                        }
-                    }
-                    None => {
-                        // スレッドiが処理中でない場合は
-                        // 待機終了
-                        break;
-                    }
-                }
-            }
-        }
-        fence(Ordering::SeqCst);
-        LockGuard { idx }
+    fn count(&self) -> u64 {
+        *self.lock()
     }
 }
 
-// ロック管理用の型
-struct LockGuard {
-    idx: usize,
-}
+impl Counter for TicketLock<u64> {
+    fn run(&self, cs: &dyn Fn()) {
+        for _ in 0..NUM_LOOP {
+            let mut count = self.lock();
+            cs();
+            *count += 1;
+        }
+    }
 
-impl Drop for LockGuard {
-    // ロック解放処理
-    fn drop(&mut self) {
-        fence(Ordering::SeqCst);
-        write_mem!(&mut LOCK.tickets[self.idx], None);
+    fn count(&self) -> u64 {
+        *self.lock()
     }
 }
 
-// グローバル変数
-static mut LOCK: BakeryLock = BakeryLock {
-    entering: [false; NUM_THREADS],
-    tickets: [None; NUM_THREADS],
-};
+// `cargo run -- test`の場合は、クリティカルセクション内のスレッド数も検査する
+fn main() {
+    let test = std::env::args().nth(1).as_deref() == Some("test");
 
-static mut COUNT: u64 = 0;
+    bench("BakeryLock", BakeryLock::<u64, NUM_THREADS>::new(0), test);
+    bench("SpinLock", SpinLock::new(0), test);
+    bench("TicketLock", TicketLock::new(0), test);
 
-fn main() {
+    register();
+}
+
+fn bench<L: Counter>(name: &str, lock: L, test: bool) {
+    let lock = Arc::new(lock);
+    let in_cs = Arc::new(AtomicUsize::new(0)); // クリティカルセクション内のスレッド数
     let mut v = Vec::new();
-    for i in 0..NUM_THREADS {
+
+    let start = Instant::now();
+    for _ in 0..NUM_THREADS {
+        let lock0 = lock.clone();
+        let in_cs0 = in_cs.clone();
         let th = thread::spawn(move || {
-            // NUM_LOOPだけループしてCOUNTをインクリメント
-            let _lock = unsafe { LOCK.lock(i) };
-            unsafe {
-                let c = read_volatile(&COUNT);
-                write_volatile(&mut COUNT, c + 1);
+            if test {
+                lock0.run(&|| {
+                    // 相互排除されていれば、自分以外は存在しない
+                    assert_eq!(in_cs0.fetch_add(1, Ordering::SeqCst), 0);
+                    in_cs0.fetch_sub(1, Ordering::SeqCst);
+                });
+            } else {
+                lock0.run(&|| {});
             }
         });
         v.push(th);
@@ -123,10 +100,36 @@ fn main() {
     for th in v {
         th.join().unwrap();
     }
+    let elapsed = start.elapsed();
 
+    let count = lock.count();
     println!(
-        "COUNT = {} (expected = {})",
-        unsafe { COUNT },
-        NUM_LOOP * NUM_THREADS
+        "{}: COUNT = {} (expected = {}), {:?}",
+        name,
+        count,
+        NUM_LOOP * NUM_THREADS,
+        elapsed
     );
+    assert_eq!(count, (NUM_LOOP * NUM_THREADS) as u64);
+}
+
+// スロットの確保と解放を確認
+fn register() {
+    let lock = BakeryLock::<u64, NUM_THREADS>::new(0);
+
+    // スロットをすべて確保すると、それ以上は確保できない
+    let mut handles: Vec<_> = (0..NUM_THREADS).map(|_| lock.register().unwrap()).collect();
+    assert!(lock.register().is_none());
+
+    // 解放したスロットは再利用される
+    let h = handles.remove(1);
+    assert_eq!(h.index(), 1);
+    drop(h);
+    let mut h = lock.register().unwrap();
+    assert_eq!(h.index(), 1);
+
+    *h.lock() += 1;
+    *handles[0].lock() += 1;
+    assert_eq!(*h.lock(), 2);
+    println!("register: ok");
 }
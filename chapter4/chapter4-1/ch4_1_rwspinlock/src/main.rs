@@ -0,0 +1,100 @@
+use std::sync::Arc;
+use std::thread;
+
+mod rwspinlock;
+
+use rwspinlock::RwSpinLock;
+
+const NUM_THREADS: usize = 4;
+const NUM_LOOP: usize = 100000;
+
+fn main() {
+    upgrade();
+    writer_preference();
+    counter();
+}
+
+// ch4_1_rwlock_1_1をアップグレード可能なReadロックで書き換えたもの
+fn upgrade() {
+    let val = Arc::new(RwSpinLock::new(true));
+
+    let val0 = val.clone();
+    let t = thread::spawn(move || {
+        // アップグレード可能なReadロックを獲得
+        let flag = val0.upgradable_read();
+        if *flag {
+            // Readロックを解放せずにWriteロックへアップグレード。
+            // デッドロックは発生しない
+            *flag.upgrade() = false;
+            println!("flag is true");
+        }
+    });
+
+    t.join().unwrap();
+    assert!(!*val.read());
+}
+
+// Writeロックの獲得待ちがいる間は、新たにReadロックを獲得できないことを確認
+fn writer_preference() {
+    let val = Arc::new(RwSpinLock::new(0));
+    let guard = val.read();
+
+    let val0 = val.clone();
+    let t = thread::spawn(move || {
+        *val0.write() += 1;
+    });
+
+    // Writerが獲得待ちになるまで待機
+    while !val.writer_waiting() {
+        thread::yield_now();
+    }
+    // 獲得待ちのWriterがいる間は、Readロックを獲得できない
+    assert!(val.try_read().is_none());
+    drop(guard);
+    t.join().unwrap();
+
+    assert_eq!(*val.read(), 1);
+    println!("writer preference: ok");
+}
+
+// 複数のReader、Writer、アップグレードするスレッドが混在しても整合性が保たれることを確認
+fn counter() {
+    // 常に同じ値となる2つの値
+    let lock = Arc::new(RwSpinLock::new((0, 0)));
+    let mut v = Vec::new();
+
+    for i in 0..NUM_THREADS {
+        let lock0 = lock.clone();
+        let t = thread::spawn(move || {
+            for _ in 0..NUM_LOOP {
+                match i {
+                    0 => {
+                        let mut data = lock0.write();
+                        data.0 += 1;
+                        data.1 += 1;
+                    }
+                    1 => {
+                        let data = lock0.upgradable_read();
+                        assert_eq!(data.0, data.1);
+                        let mut data = data.upgrade();
+                        data.0 += 1;
+                        data.1 += 1;
+                    }
+                    _ => {
+                        let data = lock0.read();
+                        assert_eq!(data.0, data.1);
+                    }
+                }
+            }
+        });
+        v.push(t);
+    }
+
+    for t in v {
+        t.join().unwrap();
+    }
+
+    let data = Arc::try_unwrap(lock).ok().unwrap().into_inner();
+    println!("COUNT = {} (expected = {})", data.0, NUM_LOOP * 2);
+    assert_eq!(data, (NUM_LOOP * 2, NUM_LOOP * 2));
+}
@@ -0,0 +1,207 @@
+use std::cell::UnsafeCell;
+use std::mem::ManuallyDrop;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// 状態を表すビット。上位ビットはReadロックの獲得数
+const WRITER: usize = 1; // Writeロック獲得中
+const UPGRADABLE: usize = 1 << 1; // アップグレード可能なReadロック獲得中
+const WRITER_WAITING: usize = 1 << 2; // Writeロックの獲得待ちがいる
+const READER: usize = 1 << 3; // Readロック1つ分
+
+// Writeロックを優先するReader-Writerスピンロック
+// Writeロックの獲得待ちがいる間は、新たにReadロックを獲得できない
+pub struct RwSpinLock<T> {
+    state: AtomicUsize,
+    data: UnsafeCell<T>,
+}
+
+// Readロックを解放し、保護対象データを読み込むための型
+pub struct RwSpinLockReadGuard<'a, T> {
+    rwlock: &'a RwSpinLock<T>,
+}
+
+// Writeロックを解放し、保護対象データを書き込むための型
+pub struct RwSpinLockWriteGuard<'a, T> {
+    rwlock: &'a RwSpinLock<T>,
+}
+
+// Writeロックへアップグレード可能なReadロックのための型
+// 同時に獲得できるのは1スレッドのみで、通常のReadロックとは共存可能
+pub struct RwSpinLockUpgradableGuard<'a, T> {
+    rwlock: &'a RwSpinLock<T>,
+}
+
+impl<T> RwSpinLock<T> {
+    pub fn new(v: T) -> Self {
+        RwSpinLock {
+            state: AtomicUsize::new(0),
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    pub fn read(&self) -> RwSpinLockReadGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_read() {
+                return guard;
+            }
+            std::hint::spin_loop();
+        }
+    }
+
+    // Writeロック獲得中か、その獲得待ちがいる場合のみNoneをリターン
+    // 他のReaderとの競合でCASに失敗した場合は再試行する
+    pub fn try_read(&self) -> Option<RwSpinLockReadGuard<'_, T>> {
+        let mut s = self.state.load(Ordering::Relaxed);
+        loop {
+            if s & (WRITER | WRITER_WAITING) != 0 {
+                return None;
+            }
+            match self
+                .state
+                .compare_exchange(s, s + READER, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => return Some(RwSpinLockReadGuard { rwlock: self }),
+                Err(x) => s = x,
+            }
+        }
+    }
+
+    pub fn write(&self) -> RwSpinLockWriteGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_write() {
+                return guard;
+            }
+            // 獲得待ちであることを示し、新たなReadロックの獲得を止める
+            let s = self.state.load(Ordering::Relaxed);
+            if s & WRITER_WAITING == 0 {
+                self.state.fetch_or(WRITER_WAITING, Ordering::Relaxed);
+            }
+            std::hint::spin_loop();
+        }
+    }
+
+    // いずれかのロックが獲得中の場合のみNoneをリターン
+    pub fn try_write(&self) -> Option<RwSpinLockWriteGuard<'_, T>> {
+        let mut s = self.state.load(Ordering::Relaxed);
+        loop {
+            if s & !WRITER_WAITING != 0 {
+                return None;
+            }
+            // 獲得時に獲得待ちのビットをクリア
+            // 他にも獲得待ちのWriterがいれば、再度セットされる
+            match self
+                .state
+                .compare_exchange(s, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => return Some(RwSpinLockWriteGuard { rwlock: self }),
+                // 獲得待ちのビットのみ変化した場合は再試行
+                Err(x) => s = x,
+            }
+        }
+    }
+
+    // Writeロックの獲得待ちがいるか。監視用のため、値は概算
+    pub fn writer_waiting(&self) -> bool {
+        self.state.load(Ordering::Relaxed) & WRITER_WAITING != 0
+    }
+
+    pub fn upgradable_read(&self) -> RwSpinLockUpgradableGuard<'_, T> {
+        loop {
+            let s = self.state.load(Ordering::Relaxed);
+            if s & (WRITER | UPGRADABLE | WRITER_WAITING) == 0
+                && self
+                    .state
+                    .compare_exchange_weak(s, s | UPGRADABLE, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return RwSpinLockUpgradableGuard { rwlock: self };
+            }
+            std::hint::spin_loop();
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<'a, T> RwSpinLockUpgradableGuard<'a, T> {
+    // Writeロックへアップグレード
+    // 他のWriterはUPGRADABLEがセットされている間は獲得できないため、
+    // ロックを解放することなく、他のReadロックがすべて解放されるまで待機するだけで良い
+    pub fn upgrade(self) -> RwSpinLockWriteGuard<'a, T> {
+        let rwlock = ManuallyDrop::new(self).rwlock;
+
+        // 新たなReadロックの獲得を止める
+        rwlock.state.fetch_or(WRITER_WAITING, Ordering::Relaxed);
+        loop {
+            let s = rwlock.state.load(Ordering::Relaxed);
+            if s & !WRITER_WAITING == UPGRADABLE
+                && rwlock
+                    .state
+                    .compare_exchange_weak(s, WRITER, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return RwSpinLockWriteGuard { rwlock };
+            }
+            std::hint::spin_loop();
+        }
+    }
+}
+
+// RwSpinLock型はスレッド間で共有可能と指定
+// Readロックにより複数スレッドから&Tを得るため、T: Syncも必要
+unsafe impl<T: Send + Sync> Sync for RwSpinLock<T> {}
+unsafe impl<T: Send> Send for RwSpinLock<T> {}
+
+impl<'a, T> Drop for RwSpinLockReadGuard<'a, T> {
+    fn drop(&mut self) {
+        self.rwlock.state.fetch_sub(READER, Ordering::Release);
+    }
+}
+
+impl<'a, T> Drop for RwSpinLockWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        // 獲得待ちのビットは残す
+        self.rwlock.state.fetch_and(!WRITER, Ordering::Release);
+    }
+}
+
+impl<'a, T> Drop for RwSpinLockUpgradableGuard<'a, T> {
+    fn drop(&mut self) {
+        self.rwlock.state.fetch_and(!UPGRADABLE, Ordering::Release);
+    }
+}
+
+// 保護対象データのimmutableな参照外し
+impl<'a, T> Deref for RwSpinLockReadGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.rwlock.data.get() }
+    }
+}
+
+impl<'a, T> Deref for RwSpinLockUpgradableGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.rwlock.data.get() }
+    }
+}
+
+impl<'a, T> Deref for RwSpinLockWriteGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.rwlock.data.get() }
+    }
+}
+
+// 保護対象データのmutableな参照外し
+impl<'a, T> DerefMut for RwSpinLockWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.rwlock.data.get() }
+    }
+}
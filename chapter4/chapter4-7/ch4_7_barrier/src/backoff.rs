@@ -0,0 +1,36 @@
+// バックオフでスピンする回数の上限は2^MAX_STEP回
+const MAX_STEP: u32 = 6;
+
+// 待機のたびにスピン回数を倍にする指数バックオフ
+pub(crate) struct Backoff {
+    step: u32,
+    spins: usize,               // これまでにスピンした回数
+    yield_after: Option<usize>, // この回数を超えてスピンした後はスレッドを明け渡す
+}
+
+impl Backoff {
+    pub(crate) fn new(yield_after: Option<usize>) -> Self {
+        Backoff {
+            step: 0,
+            spins: 0,
+            yield_after,
+        }
+    }
+
+    pub(crate) fn snooze(&mut self) {
+        if let Some(n) = self.yield_after {
+            if self.spins >= n {
+                std::thread::yield_now();
+                return;
+            }
+        }
+
+        for _ in 0..1 << self.step {
+            std::hint::spin_loop();
+        }
+        self.spins += 1 << self.step;
+        if self.step < MAX_STEP {
+            self.step += 1;
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use crate::backoff::Backoff;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// n個のスレッドがwaitを呼び出すまで、スピンして待機するバリア
+// 世代番号を用いるため、再生成せずに繰り返し利用可能
+pub struct SpinBarrier {
+    n: usize,
+    count: AtomicUsize,      // 現在の世代で到着したスレッド数
+    generation: AtomicUsize, // 世代番号。全スレッドが到着するたびにインクリメント
+}
+
+// waitの結果
+pub struct BarrierWaitResult {
+    leader: bool,
+}
+
+impl BarrierWaitResult {
+    // 各世代で最後に到着した1スレッドのみtrue
+    pub fn is_leader(&self) -> bool {
+        self.leader
+    }
+}
+
+impl SpinBarrier {
+    pub fn new(n: usize) -> Self {
+        assert!(n > 0);
+        SpinBarrier {
+            n,
+            count: AtomicUsize::new(0),
+            generation: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn wait(&self) -> BarrierWaitResult {
+        // 到着前に世代番号を読み込む
+        // 前の世代のwaitから抜けたスレッドは、必ず現在の世代番号を読み込む
+        let generation = self.generation.load(Ordering::Acquire);
+
+        if self.count.fetch_add(1, Ordering::AcqRel) + 1 == self.n {
+            // 最後に到着したスレッドが次の世代のためにリセットし、
+            // 世代番号をインクリメントして待機中のスレッドを解放
+            // Releaseにより、リセットは次の世代のスレッドから観測される
+            self.count.store(0, Ordering::Relaxed);
+            self.generation.fetch_add(1, Ordering::Release);
+            return BarrierWaitResult { leader: true };
+        }
+
+        // 世代番号が変わるまで待機
+        let mut backoff = Backoff::new(None);
+        while self.generation.load(Ordering::Acquire) == generation {
+            backoff.snooze();
+        }
+        BarrierWaitResult { leader: false }
+    }
+}
@@ -0,0 +1,6 @@
+mod backoff;
+mod barrier;
+mod spinlock;
+
+pub use barrier::{BarrierWaitResult, SpinBarrier};
+pub use spinlock::{SpinLock, SpinLockGuard};
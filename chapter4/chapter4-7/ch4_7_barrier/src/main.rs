@@ -1,74 +1,58 @@
-use std::cell::UnsafeCell;
-use std::ops::{Deref, DerefMut};
-use std::sync::atomic::{AtomicBool, Ordering};
+use ch4_7_barrier::{SpinBarrier, SpinLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 const NUM_THREADS: usize = 4;
 const NUM_LOOP: usize = 100000;
+const NUM_PHASE: usize = 10;
 
-struct SpinLock<T> {
-    lock: AtomicBool,
-    data: UnsafeCell<T>,
-}
-
-// ロックの解放および、ロック中に保護対象データを操作するための型
-struct SpinLockGuard<'a, T> {
-    spin_lock: &'a SpinLock<T>,
-}
-
-impl<T> SpinLock<T> {
-    fn new(v: T) -> Self {
-        SpinLock {
-            lock: AtomicBool::new(false),
-            data: UnsafeCell::new(v),
-        }
-    }
-
-    fn lock(&self) -> SpinLockGuard<T> {
-        loop {
-            while self.lock.load(Ordering::Relaxed) {}
+fn main() {
+    let lock = Arc::new(SpinLock::new(0));
+    let mut v = Vec::new();
 
-            if let Ok(_) =
-                self.lock
-                    // Atomic変数と第一引数が同じであるか確認し、同じであれば第二引数の値を第三引数のメモリ順序を指定してAtomic変数に設定する。
-                    .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
-            {
-                break;
+    for _ in 0..NUM_THREADS {
+        let lock0 = lock.clone();
+        let t = std::thread::spawn(move || {
+            for _ in 0..NUM_LOOP {
+                let mut data = lock0.lock();
+                *data += 1;
             }
-        }
-        SpinLockGuard { spin_lock: self }
+        });
+        v.push(t);
+    }
+    for t in v {
+        t.join().unwrap();
     }
-}
 
-// SpinLock型はスレッド間で共有可能と指定
-unsafe impl<T> Sync for SpinLock<T> {}
-unsafe impl<T> Send for SpinLock<T> {}
+    println!(
+        "COUNT = {} (expected = {})",
+        *lock.lock(),
+        NUM_LOOP * NUM_THREADS
+    );
 
-// ロック獲得後に自動で解放されるようにDropトレイトを実装
-impl<'a, T> Drop for SpinLockGuard<'a, T> {
-    fn drop(&mut self) {
-        self.spin_lock.lock.store(false, Ordering::Release);
-    }
+    try_lock();
+    yield_lock();
+    barrier();
 }
 
-// 保護対象データのimmutableな参照外し
-impl<'a, T> Deref for SpinLockGuard<'a, T> {
-    type Target = T;
+// try_lock、get_mut、into_innerの動作を確認
+fn try_lock() {
+    let mut lock = SpinLock::new(0);
 
-    fn deref(&self) -> &Self::Target {
-        unsafe { &*self.spin_lock.data.get() }
-    }
-}
+    let guard = lock.try_lock().unwrap();
+    // ロック中は獲得できない
+    assert!(lock.try_lock().is_none());
+    drop(guard);
+    *lock.try_lock().unwrap() += 1;
 
-// 保護対象データのmutableな参照外し
-impl<'a, T> DerefMut for SpinLockGuard<'a, T> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe { &mut *self.spin_lock.data.get() }
-    }
+    *lock.get_mut() += 1;
+    assert_eq!(lock.into_inner(), 2);
+    println!("try_lock: ok");
 }
 
-fn main() {
-    let lock = Arc::new(SpinLock::new(0));
+// スレッドを明け渡すモードでもカウンタが一致することを確認
+fn yield_lock() {
+    let lock = Arc::new(SpinLock::with_yield(0, 100));
     let mut v = Vec::new();
 
     for _ in 0..NUM_THREADS {
@@ -85,9 +69,50 @@ fn main() {
         t.join().unwrap();
     }
 
-    println!(
-        "COUNT = {} (expected = {})",
-        *lock.lock(),
-        NUM_LOOP * NUM_THREADS
-    );
+    let count = Arc::try_unwrap(lock).ok().unwrap().into_inner();
+    assert_eq!(count, NUM_LOOP * NUM_THREADS);
+    println!("yield: COUNT = {}", count);
+}
+
+// 全スレッドがフェーズkを終えるまで、フェーズk+1へ進まないことを確認
+fn barrier() {
+    let barrier = Arc::new(SpinBarrier::new(NUM_THREADS));
+    // フェーズ毎の、終了したスレッド数とリーダーの数
+    let done: Arc<Vec<AtomicUsize>> =
+        Arc::new((0..NUM_PHASE).map(|_| AtomicUsize::new(0)).collect());
+    let leaders: Arc<Vec<AtomicUsize>> =
+        Arc::new((0..NUM_PHASE).map(|_| AtomicUsize::new(0)).collect());
+    let mut v = Vec::new();
+
+    for _ in 0..NUM_THREADS {
+        let barrier0 = barrier.clone();
+        let done0 = done.clone();
+        let leaders0 = leaders.clone();
+        let t = std::thread::spawn(move || {
+            for k in 0..NUM_PHASE {
+                // 前のフェーズは全スレッドが終了済みで、このフェーズは誰も終了していない
+                if k > 0 {
+                    assert_eq!(done0[k - 1].load(Ordering::Relaxed), NUM_THREADS);
+                }
+                assert!(done0[k].load(Ordering::Relaxed) < NUM_THREADS);
+
+                done0[k].fetch_add(1, Ordering::Relaxed);
+                if barrier0.wait().is_leader() {
+                    leaders0[k].fetch_add(1, Ordering::Relaxed);
+                }
+
+                assert_eq!(done0[k].load(Ordering::Relaxed), NUM_THREADS);
+            }
+        });
+        v.push(t);
+    }
+    for t in v {
+        t.join().unwrap();
+    }
+
+    // 各フェーズのリーダーはちょうど1スレッド
+    for l in leaders.iter() {
+        assert_eq!(l.load(Ordering::Relaxed), 1);
+    }
+    println!("barrier: ok");
 }
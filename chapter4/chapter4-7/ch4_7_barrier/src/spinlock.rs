@@ -0,0 +1,102 @@
+use crate::backoff::Backoff;
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub struct SpinLock<T> {
+    lock: AtomicBool,
+    yield_after: Option<usize>,
+    data: UnsafeCell<T>,
+}
+
+// ロックの解放および、ロック中に保護対象データを操作するための型
+pub struct SpinLockGuard<'a, T> {
+    spin_lock: &'a SpinLock<T>,
+    // ガードは&mut Tとして振る舞うため、共有にはT: Syncを要求する
+    _marker: PhantomData<&'a mut T>,
+}
+
+impl<T> SpinLock<T> {
+    pub fn new(v: T) -> Self {
+        SpinLock {
+            lock: AtomicBool::new(false),
+            yield_after: None,
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    // spins回スピンしても獲得できない場合は、
+    // 以降スピンせずにスレッドを明け渡して待機するスピンロック
+    // ロックを保持するスレッドとCPUを共有する場合に有効
+    pub fn with_yield(v: T, spins: usize) -> Self {
+        SpinLock {
+            lock: AtomicBool::new(false),
+            yield_after: Some(spins),
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        let mut backoff = Backoff::new(self.yield_after);
+        loop {
+            // 解放されるまでは読み込みのみ行う
+            while self.lock.load(Ordering::Relaxed) {
+                backoff.snooze();
+            }
+
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+        }
+    }
+
+    // 待機せずにロックの獲得を試みる
+    // 他のスレッドがロック中の場合はNoneをリターン
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+        // Atomic変数と第一引数が同じであるか確認し、同じであれば第二引数の値を第三引数のメモリ順序を指定してAtomic変数に設定する。
+        self.lock
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| SpinLockGuard {
+                spin_lock: self,
+                _marker: PhantomData,
+            })
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
+    // &mut selfを借用しているため、ロックせずにアクセス可能
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+}
+
+// SpinLock型はスレッド間で共有可能と指定
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+unsafe impl<T: Send> Send for SpinLock<T> {}
+
+// ロック獲得後に自動で解放されるようにDropトレイトを実装
+impl<'a, T> Drop for SpinLockGuard<'a, T> {
+    fn drop(&mut self) {
+        self.spin_lock.lock.store(false, Ordering::Release);
+    }
+}
+
+// 保護対象データのimmutableな参照外し
+impl<'a, T> Deref for SpinLockGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.spin_lock.data.get() }
+    }
+}
+
+// 保護対象データのmutableな参照外し
+impl<'a, T> DerefMut for SpinLockGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.spin_lock.data.get() }
+    }
+}
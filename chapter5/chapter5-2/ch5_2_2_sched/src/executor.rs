@@ -0,0 +1,412 @@
+use crate::join::{Completer, JoinError, JoinHandle};
+use futures::future::{BoxFuture, FutureExt};
+use futures::task::{waker_ref, ArcWake};
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::pin;
+use std::sync::atomic::{fence, AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::Thread;
+
+thread_local! {
+    // このスレッドで実行中のワーカー。(Executorの識別子, ワーカー番号)
+    static WORKER: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+type Queue = Mutex<VecDeque<Arc<Task>>>;
+
+pub(crate) struct Task {
+    // 実行するコルーチン。完了後はNone
+    future: Mutex<Option<BoxFuture<'static, ()>>>,
+    // スケジューリング先のExecutor
+    shared: Arc<Shared>,
+    // abortされたか
+    aborted: AtomicBool,
+    // 実行キューに入っているか。多重にエンキューしないために用いる
+    scheduled: AtomicBool,
+}
+
+impl Task {
+    fn id(self: &Arc<Self>) -> usize {
+        Arc::as_ptr(self) as usize
+    }
+
+    // 中断を指示してスケジューリング
+    // 実際の破棄は、ワーカーが次に取り出した際に行う
+    pub(crate) fn abort(self: &Arc<Self>) {
+        if !self.aborted.swap(true, Ordering::Relaxed) {
+            self.shared.schedule(self.clone());
+        }
+    }
+
+    // Futureを破棄
+    // Futureのドロップ中にspawnやwakeされても良いように、ロックを解放してからドロップ
+    fn drop_future(&self) {
+        let fut = self.future.lock().unwrap().take();
+        drop(fut);
+    }
+}
+
+impl ArcWake for Task {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        // 自身をスケジューリング
+        arc_self.shared.schedule(arc_self.clone());
+    }
+}
+
+// ワーカー間で共有する状態
+struct Shared {
+    // ワーカー外からスケジューリングされたタスクのキュー
+    injector: Queue,
+    // ワーカー毎のローカルキュー
+    locals: Vec<Queue>,
+    // 完了していないタスク
+    tasks: Mutex<HashMap<usize, Arc<Task>>>,
+    // 待機中のワーカー数
+    sleepers: Mutex<usize>,
+    cond: Condvar,
+    // shutdown済みか。以降はタスクを実行しない
+    shutdown: AtomicBool,
+}
+
+impl Shared {
+    fn id(self: &Arc<Self>) -> usize {
+        Arc::as_ptr(self) as usize
+    }
+
+    // タスクを実行キューにエンキュー
+    // ワーカーからは自身のローカルキューへ、それ以外からはinjectorへ
+    // キューは上限がないため、呼び出し元がブロックされることはない
+    fn schedule(self: &Arc<Self>, task: Arc<Task>) {
+        // shutdown後はエンキューせずに破棄
+        if self.shutdown.load(Ordering::Acquire) {
+            return;
+        }
+
+        // 既に実行キューに入っている場合は、次のpollで処理されるため何もしない
+        if task.scheduled.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let id = self.id();
+        match WORKER.with(|w| w.get()) {
+            Some((e, idx)) if e == id => self.locals[idx].lock().unwrap().push_back(task),
+            _ => self.injector.lock().unwrap().push_back(task),
+        }
+
+        // 待機中のワーカーがいれば起床
+        let sleepers = self.sleepers.lock().unwrap();
+        if *sleepers > 0 {
+            self.cond.notify_one();
+        }
+    }
+
+    // 実行するタスクを探す
+    // ローカルキュー、injector、他のワーカーのローカルキューの順
+    fn find_task(&self, idx: usize) -> Option<Arc<Task>> {
+        if let Some(task) = self.locals[idx].lock().unwrap().pop_front() {
+            return Some(task);
+        }
+        if let Some(task) = self.injector.lock().unwrap().pop_front() {
+            return Some(task);
+        }
+        self.steal(idx)
+    }
+
+    // 他のワーカーのローカルキューから、後ろ半分を盗む
+    fn steal(&self, idx: usize) -> Option<Arc<Task>> {
+        let n = self.locals.len();
+        for i in 1..n {
+            let victim = (idx + i) % n;
+            let mut stolen = {
+                let mut q = self.locals[victim].lock().unwrap();
+                let len = q.len();
+                q.split_off(len - len.div_ceil(2))
+            };
+            if let Some(task) = stolen.pop_front() {
+                // 残りは自身のローカルキューへ
+                self.locals[idx].lock().unwrap().extend(stolen);
+                return Some(task);
+            }
+        }
+        None
+    }
+
+    fn has_task(&self) -> bool {
+        !self.injector.lock().unwrap().is_empty()
+            || self.locals.iter().any(|q| !q.lock().unwrap().is_empty())
+    }
+
+    // ワーカーを終了するか
+    // stopがセットされるか、until_idleの場合は完了していないタスクがなくなった時点で終了
+    fn stopped(&self, until_idle: bool, stop: &AtomicBool) -> bool {
+        stop.load(Ordering::Acquire)
+            || self.shutdown.load(Ordering::Acquire)
+            || (until_idle && self.tasks.lock().unwrap().is_empty())
+    }
+
+    // タスクがスケジューリングされるか、終了するまで待機
+    fn sleep(&self, until_idle: bool, stop: &AtomicBool) {
+        let mut sleepers = self.sleepers.lock().unwrap();
+        // sleepersのロック中に検査するため、検査後のscheduleによる起床を取りこぼさない
+        if self.has_task() || self.stopped(until_idle, stop) {
+            return;
+        }
+        *sleepers += 1;
+        sleepers = self.cond.wait(sleepers).unwrap();
+        *sleepers -= 1;
+    }
+
+    // 待機中のワーカーをすべて起床し、終了条件を再検査させる
+    fn notify_all(&self) {
+        let _sleepers = self.sleepers.lock().unwrap();
+        self.cond.notify_all();
+    }
+
+    // 完了または破棄されたタスクを削除
+    fn finish(&self, task: &Arc<Task>) {
+        let mut tasks = self.tasks.lock().unwrap();
+        tasks.remove(&task.id());
+        if tasks.is_empty() {
+            drop(tasks);
+            self.notify_all();
+        }
+    }
+
+    // ワーカーの処理
+    fn work(self: &Arc<Self>, idx: usize, until_idle: bool, stop: &AtomicBool) {
+        WORKER.with(|w| w.set(Some((self.id(), idx))));
+        while !self.stopped(until_idle, stop) {
+            let Some(task) = self.find_task(idx) else {
+                self.sleep(until_idle, stop);
+                continue;
+            };
+
+            // poll中のwakeで再度エンキューされるよう、poll前にクリア
+            // AcqRelにより、重複したwakeの前の書き込みもpollから観測される
+            task.scheduled.swap(false, Ordering::AcqRel);
+
+            let mut future = task.future.lock().unwrap();
+            let Some(fut) = future.as_mut() else {
+                // 完了済み
+                continue;
+            };
+
+            let done = if task.aborted.load(Ordering::Relaxed) {
+                // pollせずに破棄。JoinHandleにはCancelledが渡される
+                true
+            } else {
+                // コンテキストを生成
+                let waker = waker_ref(&task);
+                let mut ctx = Context::from_waker(&waker);
+                // pollを呼び出し実行
+                // poll中にabortやshutdownされた場合も破棄
+                fut.as_mut().poll(&mut ctx).is_ready() || task.aborted.load(Ordering::Relaxed)
+            };
+
+            drop(future);
+            if !done {
+                // abortedの検査後、ロックの解放前にshutdownされた場合、
+                // shutdownはロックを獲得できず、破棄をワーカーに任せている
+                // shutdown側のフェンスと対になり、ロックの解放とabortedのいずれかが必ず観測される
+                fence(Ordering::SeqCst);
+                if !task.aborted.load(Ordering::Relaxed) {
+                    continue;
+                }
+            }
+            task.drop_future();
+            self.finish(&task);
+        }
+        WORKER.with(|w| w.set(None));
+    }
+
+    // ワーカーをn個起動し、fを実行した後に停止
+    // 停止はこの呼び出しのワーカーのみで、並行して実行中のrunには影響しない
+    fn run_workers<R>(self: &Arc<Self>, f: impl FnOnce() -> R) -> R {
+        let stop = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            let stop = &stop;
+            for idx in 0..self.locals.len() {
+                s.spawn(move || self.work(idx, false, stop));
+            }
+            let result = f();
+            stop.store(true, Ordering::Release);
+            self.notify_all();
+            result
+        })
+    }
+}
+
+// block_onを呼び出したスレッドを起床するWaker
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+// 複数のワーカースレッドでタスクを実行するExecutor
+// 各ワーカーはローカルキューを持ち、空になると他のワーカーからタスクを盗む
+pub struct Executor {
+    shared: Arc<Shared>,
+}
+
+impl Executor {
+    // CPU数と同じ数のワーカーで実行するExecutorを生成
+    pub fn new() -> Self {
+        let n = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Self::with_workers(n)
+    }
+
+    // n個のワーカーで実行するExecutorを生成
+    pub fn with_workers(n: usize) -> Self {
+        assert!(n > 0);
+        Executor {
+            shared: Arc::new(Shared {
+                injector: Mutex::new(VecDeque::new()),
+                locals: (0..n).map(|_| Mutex::new(VecDeque::new())).collect(),
+                tasks: Mutex::new(HashMap::new()),
+                sleepers: Mutex::new(0),
+                cond: Condvar::new(),
+                shutdown: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    // 新たにTaskを生成するためのSpawnerを作成
+    pub fn get_spawner(&self) -> Spawner {
+        Spawner {
+            shared: self.shared.clone(),
+        }
+    }
+
+    // ワーカースレッドを起動して実行
+    // 完了していないタスクがなくなるか、shutdownされるとリターン
+    pub fn run(&self) {
+        let shared = &self.shared;
+        let stop = &AtomicBool::new(false);
+        // 呼び出したスレッドもワーカー0として実行する
+        std::thread::scope(|s| {
+            for idx in 1..shared.locals.len() {
+                s.spawn(move || shared.work(idx, true, stop));
+            }
+            shared.work(0, true, stop);
+        });
+    }
+
+    // ワーカースレッドでタスクを実行しながら、futureが完了するまで待機して結果をリターン
+    // futureは呼び出したスレッドでpollされるため、Sendである必要はない
+    // リターン時に完了していないタスクは、次のrunやblock_onで実行される
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        let mut future = pin!(future);
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        let mut ctx = Context::from_waker(&waker);
+
+        self.shared.run_workers(|| loop {
+            if let Poll::Ready(result) = future.as_mut().poll(&mut ctx) {
+                return result;
+            }
+            std::thread::park();
+        })
+    }
+
+    // 完了していないタスクをすべて破棄し、実行中のrunをリターンさせる
+    // 破棄したタスクのJoinHandleはCancelledとなる
+    // 以降にspawnされたタスクは実行されずに破棄される
+    pub fn shutdown(&self) {
+        let shared = &self.shared;
+        // spawnとの競合で取りこぼさないよう、tasksのロック中にフラグをセット
+        let tasks: Vec<_> = {
+            let mut tasks = shared.tasks.lock().unwrap();
+            shared.shutdown.store(true, Ordering::Release);
+            tasks.drain().map(|(_, t)| t).collect()
+        };
+        shared.notify_all();
+
+        shared.injector.lock().unwrap().clear();
+        for q in shared.locals.iter() {
+            q.lock().unwrap().clear();
+        }
+
+        for task in tasks.iter() {
+            task.aborted.store(true, Ordering::Relaxed);
+        }
+        // ワーカー側のフェンスと対になる
+        fence(Ordering::SeqCst);
+        for task in tasks {
+            // poll中のタスクは、ワーカーがロックの解放後に破棄する
+            // 自身のpoll中にshutdownを呼び出した場合もデッドロックしないよう、try_lockを用いる
+            if let Ok(mut future) = task.future.try_lock() {
+                let fut = future.take();
+                drop(future);
+                drop(fut);
+            }
+        }
+    }
+}
+
+// 完了していないタスクは、Executorからの参照がなくなると実行されないため破棄
+// Task -> Shared -> Taskの循環参照による、タスクのリークを防ぐ
+impl Drop for Executor {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone)]
+pub struct Spawner {
+    shared: Arc<Shared>,
+}
+
+impl Spawner {
+    // タスクを生成して実行キューにエンキュー
+    // 結果はリターンしたJoinHandleをawaitして得る
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        // Task生成
+        let task = Arc::new(Task {
+            future: Mutex::new(None),
+            shared: self.shared.clone(),
+            aborted: AtomicBool::new(false),
+            scheduled: AtomicBool::new(false),
+        });
+        let completer = Completer::new();
+        let handle = completer.handle(task.clone());
+
+        // パニックを捕捉し、結果をJoinHandleへ渡すFutureでラップ
+        let future = async move {
+            let result = AssertUnwindSafe(future).catch_unwind().await;
+            completer.complete(result.map_err(JoinError::Panic));
+        };
+        let future = future.boxed(); // FutureをBox化
+
+        {
+            let mut tasks = self.shared.tasks.lock().unwrap();
+            // shutdown後は登録しない。futureは破棄され、結果はCancelledとなる
+            if self.shared.shutdown.load(Ordering::Acquire) {
+                drop(tasks);
+                drop(future);
+                return handle;
+            }
+            *task.future.lock().unwrap() = Some(future);
+            tasks.insert(task.id(), task.clone());
+        }
+
+        // 実行キューにエンキュー
+        self.shared.schedule(task);
+        handle
+    }
+}
@@ -0,0 +1,127 @@
+use crate::executor::Task;
+use std::any::Any;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+// タスクが正常に完了しなかった理由
+pub enum JoinError {
+    // タスク内でパニックした。値はpanicの引数
+    Panic(Box<dyn Any + Send + 'static>),
+    // abortなどにより、完了前に破棄された
+    Cancelled,
+}
+
+impl JoinError {
+    pub fn is_panic(&self) -> bool {
+        matches!(self, JoinError::Panic(_))
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, JoinError::Cancelled)
+    }
+}
+
+impl fmt::Debug for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::Panic(_) => f.write_str("Panic(..)"),
+            JoinError::Cancelled => f.write_str("Cancelled"),
+        }
+    }
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::Panic(_) => f.write_str("task panicked"),
+            JoinError::Cancelled => f.write_str("task was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+// タスクの結果と、結果を待つJoinHandleのWaker
+struct JoinState<T> {
+    result: Option<Result<T, JoinError>>,
+    waker: Option<Waker>,
+}
+
+// タスクの結果をJoinHandleへ渡すための型
+// 結果を渡さずに破棄された場合はCancelledを渡す
+pub(crate) struct Completer<T> {
+    state: Option<Arc<Mutex<JoinState<T>>>>,
+}
+
+impl<T> Completer<T> {
+    pub(crate) fn new() -> Self {
+        Completer {
+            state: Some(Arc::new(Mutex::new(JoinState {
+                result: None,
+                waker: None,
+            }))),
+        }
+    }
+
+    // 結果を受け取るJoinHandleを生成
+    pub(crate) fn handle(&self, task: Arc<Task>) -> JoinHandle<T> {
+        JoinHandle {
+            state: self.state.clone().unwrap(),
+            task,
+        }
+    }
+
+    pub(crate) fn complete(mut self, result: Result<T, JoinError>) {
+        Self::set(self.state.take().unwrap(), result);
+    }
+
+    fn set(state: Arc<Mutex<JoinState<T>>>, result: Result<T, JoinError>) {
+        let mut state = state.lock().unwrap();
+        state.result = Some(result);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        if let Some(state) = self.state.take() {
+            Self::set(state, Err(JoinError::Cancelled));
+        }
+    }
+}
+
+// spawnしたタスクの完了を待ち、結果を得るためのFuture
+// ドロップしてもタスクは実行され続ける
+pub struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+    task: Arc<Task>,
+}
+
+impl<T> JoinHandle<T> {
+    // タスクを中断する
+    // 次にスケジューリングされた際にpollされずに破棄され、結果はCancelledとなる
+    // 完了済みの場合は何もしない
+    pub fn abort(&self) {
+        self.task.abort();
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,5 @@
+mod executor;
+mod join;
+
+pub use executor::{Executor, Spawner};
+pub use join::{JoinError, JoinHandle};
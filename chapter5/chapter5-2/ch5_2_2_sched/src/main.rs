@@ -1,10 +1,10 @@
-use futures::future::{BoxFuture, FutureExt};
-use futures::task::{waker_ref, ArcWake};
+use ch5_2_2_sched::Executor;
 use std::future::Future;
 use std::pin::Pin;
-use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
-use std::sync::{Arc, Mutex};
-use std::task::{Context, Poll};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Barrier, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
 
 struct Hello {
     state: StateHello,
@@ -49,77 +49,449 @@ impl Future for Hello {
     }
 }
 
-struct Task {
-    // 実行するコルーチン
-    future: Mutex<BoxFuture<'static, ()>>,
-    // Executorへスケジューリングするためのチャネル
-    sender: SyncSender<Arc<Task>>,
+// `cargo run --release -- bench`の場合はベンチマークを実行
+fn main() {
+    if std::env::args().nth(1).as_deref() == Some("bench") {
+        bench();
+        return;
+    }
+
+    join();
+    self_wake();
+    dedupe();
+    shutdown();
+    shutdown_mid_poll();
+    drop_executor();
+    block_on_with_run();
+
+    // 完了していないタスクがなくなるとリターン
+    let executor = Executor::new();
+    executor.get_spawner().spawn(Hello::new());
+    executor.run();
+}
+
+// JoinHandleで結果、パニック、中断を受け取れることを確認
+fn join() {
+    let executor = Executor::new();
+    let spawner = executor.get_spawner();
+
+    let n = executor.block_on(async {
+        // タスクの返り値
+        let handle = spawner.spawn(async { 1 + 2 });
+        assert_eq!(handle.await.unwrap(), 3);
+
+        // パニックしたタスク
+        let handle = spawner.spawn(async { panic!("panic in task") });
+        assert!(handle.await.unwrap_err().is_panic());
+
+        // 完了しないタスクを中断
+        let handle = spawner.spawn(futures::future::pending::<()>());
+        handle.abort();
+        assert!(handle.await.unwrap_err().is_cancelled());
+        4
+    });
+    assert_eq!(n, 4);
+    println!("join: ok");
 }
 
-impl ArcWake for Task {
-    fn wake_by_ref(arc_self: &Arc<Self>) {
-        // 自身をスケジューリング
-        let self0 = arc_self.clone();
-        arc_self.sender.send(self0).unwrap();
+const NUM_WAKE_TASKS: usize = 10000;
+const NUM_WAKE: usize = 10; // 自身を起床する回数
+
+// pollのたびに自身を2回起床するFuture
+struct WakeTwice {
+    remaining: usize,
+    polls: Arc<AtomicUsize>,
+}
+
+impl Future for WakeTwice {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.polls.fetch_add(1, Ordering::Relaxed);
+        if self.remaining == 0 {
+            return Poll::Ready(());
+        }
+        self.remaining -= 1;
+        cx.waker().wake_by_ref();
+        cx.waker().wake_by_ref(); // 重複した起床
+        Poll::Pending
     }
 }
 
-struct Executor {
-    // 実行キュー
-    sender: SyncSender<Arc<Task>>,
-    receiver: Receiver<Arc<Task>>,
+// ワーカー上で大量のタスクが自身を起床してもデッドロックしないことを確認
+fn self_wake() {
+    let executor = Executor::new();
+    let spawner = executor.get_spawner();
+    let polls = Arc::new(AtomicUsize::new(0));
+
+    let spawner0 = spawner.clone();
+    let polls0 = polls.clone();
+    let handle = spawner.spawn(async move {
+        // ワーカー上からspawnするため、すべてローカルキューに入る
+        let handles: Vec<_> = (0..NUM_WAKE_TASKS)
+            .map(|_| {
+                spawner0.spawn(WakeTwice {
+                    remaining: NUM_WAKE,
+                    polls: polls0.clone(),
+                })
+            })
+            .collect();
+        for h in handles {
+            h.await.unwrap();
+        }
+    });
+
+    executor.block_on(handle).unwrap();
+    assert_eq!(
+        polls.load(Ordering::Relaxed),
+        NUM_WAKE_TASKS * (NUM_WAKE + 1)
+    );
+    println!("self wake: ok");
 }
 
-impl Executor {
-    fn new() -> Self {
-        // チャネルを生成。キューのサイズは最大1024個
-        let (sender, receiver) = sync_channel(1024);
-        Executor {
-            sender: sender.clone(),
-            receiver,
+// 最初のpollでのみ自身を2回起床し、以降は起床しないFuture
+struct WakeTwiceOnce {
+    woken: bool,
+    polls: Arc<AtomicUsize>,
+}
+
+impl Future for WakeTwiceOnce {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.polls.fetch_add(1, Ordering::Relaxed);
+        if !self.woken {
+            self.woken = true;
+            cx.waker().wake_by_ref();
+            cx.waker().wake_by_ref(); // 重複した起床
         }
+        Poll::Pending
     }
+}
+
+// 重複した起床が1回のpollにまとめられることを確認
+fn dedupe() {
+    // キューの順序を固定するため、ワーカーは1つ
+    let executor = Executor::with_workers(1);
+    let spawner = executor.get_spawner();
+    let polls = Arc::new(AtomicUsize::new(0));
+
+    let spawner0 = spawner.clone();
+    let polls0 = polls.clone();
+    let handle = spawner.spawn(async move {
+        let handle = spawner0.spawn(WakeTwiceOnce {
+            woken: false,
+            polls: polls0.clone(),
+        });
+        // 実行を2回譲る間に、WakeTwiceOnceは最初のpollと、起床による1回のpollのみ実行される
+        // 重複した起床がまとめられない場合は、3回pollされる
+        YieldNow { yielded: false }.await;
+        YieldNow { yielded: false }.await;
+        assert_eq!(polls0.load(Ordering::Relaxed), 2);
+
+        handle.abort();
+        assert!(handle.await.unwrap_err().is_cancelled());
+    });
+
+    executor.block_on(handle).unwrap();
+    assert_eq!(polls.load(Ordering::Relaxed), 2);
+    println!("dedupe: ok");
+}
+
+const NUM_TASKS: usize = 1000;
+const NUM_YIELD: usize = 10; // CPUバウンドなタスクが実行を譲る回数
+const NUM_CALC: u64 = 100000; // 実行を譲るまでの計算回数
+const NUM_IO: usize = 10; // I/Oバウンドなタスクが待機する回数
+const IO_WAIT: Duration = Duration::from_millis(1);
+
+// 一度だけPendingをリターンし、他のタスクへ実行を譲るFuture
+struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
 
-    // 新たにTaskを生成するためのSpawnerを作成
-    fn get_spawner(&self) -> Spawner {
-        Spawner {
-            sender: self.sender.clone(),
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
         }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
     }
+}
 
-    fn run(&self) {
-        // チャネルからTaskを受信して順に実行
-        while let Ok(task) = self.receiver.recv() {
-            // コンテキストを生成
-            let mut future = task.future.lock().unwrap();
-            let waker = waker_ref(&task);
-            let mut ctx = Context::from_waker(&waker);
-            // pollを呼び出し実行
-            let _ = future.as_mut().poll(&mut ctx);
+// I/O待ちを模擬するため、期限になるとタイマスレッドから起床されるFuture
+struct Delay {
+    deadline: Instant,
+    timer: Arc<Timer>,
+    registered: bool,
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+        if !self.registered {
+            self.registered = true;
+            self.timer.register(self.deadline, cx.waker().clone());
         }
+        Poll::Pending
     }
 }
 
-struct Spawner {
-    sender: SyncSender<Arc<Task>>,
+struct Timer {
+    wakers: Mutex<Vec<(Instant, Waker)>>,
+    cond: Condvar,
+}
+
+impl Timer {
+    fn new() -> Arc<Self> {
+        let timer = Arc::new(Timer {
+            wakers: Mutex::new(Vec::new()),
+            cond: Condvar::new(),
+        });
+        let t = timer.clone();
+        std::thread::spawn(move || t.run());
+        timer
+    }
+
+    fn delay(self: &Arc<Self>, dur: Duration) -> Delay {
+        Delay {
+            deadline: Instant::now() + dur,
+            timer: self.clone(),
+            registered: false,
+        }
+    }
+
+    fn register(&self, deadline: Instant, waker: Waker) {
+        self.wakers.lock().unwrap().push((deadline, waker));
+        self.cond.notify_one();
+    }
+
+    // 期限を過ぎたWakerを起床し、次の期限まで待機
+    fn run(&self) {
+        let mut wakers = self.wakers.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            wakers.retain(|(deadline, waker)| {
+                if *deadline <= now {
+                    waker.wake_by_ref();
+                    false
+                } else {
+                    true
+                }
+            });
+            wakers = match wakers.iter().map(|(deadline, _)| *deadline).min() {
+                Some(deadline) => self.cond.wait_timeout(wakers, deadline - now).unwrap().0,
+                None => self.cond.wait(wakers).unwrap(),
+            };
+        }
+    }
 }
 
-impl Spawner {
-    fn spawn(&self, future: impl Future<Output = ()> + 'static + Send) {
-        let future = future.boxed();    // FutureをBox化
-        let task = Arc::new(Task {      // Task生成
-            future: Mutex::new(future),
-            sender: self.sender.clone(),
+// CPUバウンドなタスクとI/Oバウンドなタスクを、ワーカー数を変えて実行
+fn bench() {
+    let n = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut workers = vec![1];
+    if n > 1 {
+        workers.push(n);
+    }
+
+    let timer = Timer::new();
+    for w in workers {
+        let cpu = run_bench(w, |_| async {
+            let mut x = 88172645463325252u64;
+            for _ in 0..NUM_YIELD {
+                for _ in 0..NUM_CALC {
+                    // xorshift
+                    x ^= x << 13;
+                    x ^= x >> 7;
+                    x ^= x << 17;
+                }
+                YieldNow { yielded: false }.await;
+            }
+            x
         });
 
-        // 実行キューにエンキュー
-        self.sender.send(task).unwrap();
+        let io = run_bench(w, |_| {
+            let timer = timer.clone();
+            async move {
+                for _ in 0..NUM_IO {
+                    timer.delay(IO_WAIT).await;
+                }
+                1
+            }
+        });
+
+        println!("workers = {}: cpu = {:?}, io = {:?}", w, cpu, io);
     }
 }
 
+// NUM_TASKS個のタスクを実行し、すべて完了するまでの時間をリターン
+fn run_bench<F, Fut>(workers: usize, f: F) -> Duration
+where
+    F: Fn(usize) -> Fut,
+    Fut: Future<Output = u64> + Send + 'static,
+{
+    let executor = Executor::with_workers(workers);
+    let spawner = executor.get_spawner();
 
-fn main() {
-    let executor = Executor::new();
-    executor.get_spawner().spawn(Hello::new());
+    let start = Instant::now();
+    let handles: Vec<_> = (0..NUM_TASKS).map(|i| spawner.spawn(f(i))).collect();
+    // すべてのタスクが完了するとリターン
     executor.run();
+    let elapsed = start.elapsed();
+
+    // 計算結果が最適化で除去されないように集計
+    let sum = executor.block_on(async {
+        let mut sum = 0u64;
+        for h in handles {
+            sum = sum.wrapping_add(h.await.unwrap());
+        }
+        sum
+    });
+    assert_ne!(sum, 0);
+    elapsed
+}
+
+// 値がドロップされたことを記録するための型
+struct DropFlag(Arc<AtomicBool>);
+
+impl Drop for DropFlag {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+// shutdownで完了していないタスクが破棄され、runがリターンすることを確認
+fn shutdown() {
+    let executor = Arc::new(Executor::new());
+    let spawner = executor.get_spawner();
+    let dropped = Arc::new(AtomicBool::new(false));
+
+    // 完了しないタスク
+    let flag = DropFlag(dropped.clone());
+    let handle = spawner.spawn(async move {
+        let _flag = flag;
+        futures::future::pending::<()>().await;
+    });
+
+    // 完了しないタスクがあるため、runはshutdownされるまでリターンしない
+    let executor0 = executor.clone();
+    let t = std::thread::spawn(move || executor0.run());
+    executor.shutdown();
+    t.join().unwrap();
+
+    assert!(dropped.load(Ordering::Relaxed));
+    assert!(futures::executor::block_on(handle)
+        .unwrap_err()
+        .is_cancelled());
+
+    // shutdown後にspawnされたタスクは実行されない
+    let handle = spawner.spawn(async {});
+    assert!(futures::executor::block_on(handle)
+        .unwrap_err()
+        .is_cancelled());
+    println!("shutdown: ok");
+}
+
+const NUM_SHUTDOWN: usize = 100;
+
+// poll中にshutdownされたタスクも破棄され、JoinHandleがCancelledとなることを確認
+fn shutdown_mid_poll() {
+    let executor = Arc::new(Executor::with_workers(1));
+    let spawner = executor.get_spawner();
+    let dropped = Arc::new(AtomicBool::new(false));
+    let barrier = Arc::new(Barrier::new(2));
+
+    let flag = DropFlag(dropped.clone());
+    let barrier0 = barrier.clone();
+    let handle = spawner.spawn(async move {
+        let _flag = flag;
+        // poll中にshutdownされるまで待機し、起床せずにPendingをリターン
+        futures::future::poll_fn(|_| {
+            barrier0.wait();
+            barrier0.wait();
+            Poll::<()>::Pending
+        })
+        .await;
+    });
+
+    let executor0 = executor.clone();
+    let t = std::thread::spawn(move || executor0.run());
+    barrier.wait(); // poll開始
+    executor.shutdown();
+    barrier.wait(); // poll終了
+    t.join().unwrap();
+
+    assert!(dropped.load(Ordering::Relaxed));
+    assert!(futures::executor::block_on(handle)
+        .unwrap_err()
+        .is_cancelled());
+
+    // pollを繰り返すタスクを任意の時点でshutdownしても、JoinHandleの待機が終了する
+    for _ in 0..NUM_SHUTDOWN {
+        let executor = Arc::new(Executor::with_workers(2));
+        let handle = executor.get_spawner().spawn(async {
+            loop {
+                YieldNow { yielded: false }.await;
+            }
+        });
+        let executor0 = executor.clone();
+        let t = std::thread::spawn(move || executor0.run());
+        std::thread::yield_now();
+        executor.shutdown();
+        t.join().unwrap();
+        assert!(futures::executor::block_on(handle)
+            .unwrap_err()
+            .is_cancelled());
+    }
+    println!("shutdown mid poll: ok");
+}
+
+// Executorをドロップすると、完了していないタスクが破棄されることを確認
+fn drop_executor() {
+    let executor = Executor::new();
+    let spawner = executor.get_spawner();
+    let dropped = Arc::new(AtomicBool::new(false));
+
+    let flag = DropFlag(dropped.clone());
+    let handle = spawner.spawn(async move {
+        let _flag = flag;
+        futures::future::pending::<()>().await;
+    });
+    executor.block_on(async {});
+    drop(executor);
+
+    assert!(dropped.load(Ordering::Relaxed));
+    assert!(futures::executor::block_on(handle)
+        .unwrap_err()
+        .is_cancelled());
+    println!("drop executor: ok");
+}
+
+// block_onの完了により、並行して実行中のrunがリターンしないことを確認
+fn block_on_with_run() {
+    let executor = Arc::new(Executor::new());
+    let spawner = executor.get_spawner();
+    let handle = spawner.spawn(futures::future::pending::<()>());
+
+    let executor0 = executor.clone();
+    let t = std::thread::spawn(move || executor0.run());
+    assert_eq!(executor.block_on(async { 1 }), 1);
+    std::thread::sleep(Duration::from_millis(10));
+    assert!(!t.is_finished());
+
+    // runはshutdownでリターン
+    executor.shutdown();
+    t.join().unwrap();
+    assert!(futures::executor::block_on(handle)
+        .unwrap_err()
+        .is_cancelled());
+    println!("block_on with run: ok");
 }
@@ -1,7 +1,4 @@
-use futures::{
-    future::{BoxFuture, FutureExt},
-    task::{waker_ref, ArcWake},
-};
+use ch5_2_2_sched::Executor;
 use nix::{
     errno::Errno,
     sys::{
@@ -20,12 +17,13 @@ use std::{
     net::{SocketAddr, TcpListener, TcpStream},
     os::unix::io::{AsRawFd, RawFd},
     pin::Pin,
-    sync::{
-        mpsc::{sync_channel, Receiver, SyncSender},
-        Arc, Mutex,
-    },
+    sync::{Arc, Mutex},
     task::{Context, Poll, Waker},
+    time::{Duration, Instant},
 };
+use timer::Timers;
+
+mod timer;
 
 fn write_eventfd(fd: RawFd, n: usize) {
     // usizeを*const u8に変換
@@ -52,6 +50,8 @@ struct IOSelector {
     epfd: RawFd,
     // eventfdのfd
     event: RawFd,
+    // タイマ
+    timers: Mutex<Timers>,
 }
 
 impl IOSelector {
@@ -62,6 +62,7 @@ impl IOSelector {
             epfd: epoll_create1(EpollCreateFlags::empty()).unwrap(),
             // eventfd生成
             event: eventfd(0, EfdFlags::empty()).unwrap(),
+            timers: Mutex::new(Timers::new()),
         };
         let result = Arc::new(s);
         let s = result.clone();
@@ -122,7 +123,16 @@ impl IOSelector {
 
         let mut events = vec![EpollEvent::empty(); 1024];
         // eventの発生を監視
-        while let Ok(nfds) = epoll_wait(self.epfd, &mut events, -1) {
+        // 次のタイマの期限をタイムアウトに指定
+        loop {
+            let timeout = self.timers.lock().unwrap().next_timeout();
+            let nfds = match epoll_wait(self.epfd, &mut events, timeout) {
+                Ok(nfds) => nfds,
+                // シグナルによる中断の場合は、タイマを処理して再度待機
+                Err(nix::Error::Sys(Errno::EINTR)) => 0,
+                Err(_) => break,
+            };
+
             let mut t = self.wakers.lock().unwrap();
             for n in 0..nfds {
                 if events[n].data() == self.event as u64 {
@@ -147,6 +157,13 @@ impl IOSelector {
                     waker.wake_by_ref();
                 }
             }
+            drop(t);
+
+            // 期限を過ぎたタイマを起床
+            let expired = self.timers.lock().unwrap().expired();
+            for waker in expired {
+                waker.wake();
+            }
         }
     }
 
@@ -163,6 +180,31 @@ impl IOSelector {
         q.push_back(IOOps::REMOVE(fd));
         write_eventfd(self.event, 1);
     }
+
+    // タイマ登録用関数
+    fn add_timer(&self, deadline: Instant, waker: Waker) -> u64 {
+        let mut timers = self.timers.lock().unwrap();
+        let (id, earliest) = timers.add(deadline, waker);
+        if earliest {
+            // 最も早い期限の場合は、epoll_waitのタイムアウトを再設定させる
+            write_eventfd(self.event, 1);
+        }
+        id
+    }
+
+    fn update_timer(&self, id: u64, waker: &Waker) {
+        self.timers.lock().unwrap().update(id, waker);
+    }
+
+    // タイマ削除用関数
+    fn cancel_timer(&self, id: u64) {
+        self.timers.lock().unwrap().cancel(id);
+    }
+
+    // 登録中のタイマの数
+    fn num_timers(&self) -> usize {
+        self.timers.lock().unwrap().len()
+    }
 }
 
 struct AsyncListener {
@@ -296,74 +338,13 @@ impl<'a> Future for ReadLine<'a> {
     }
 }
 
-struct Task {
-    // 実行するコルーチン
-    future: Mutex<BoxFuture<'static, ()>>,
-    // Executorへスケジューリングするためのチャネル
-    sender: SyncSender<Arc<Task>>,
-}
-
-impl ArcWake for Task {
-    fn wake_by_ref(arc_self: &Arc<Self>) {
-        // 自身をスケジューリング
-        let self0 = arc_self.clone();
-        arc_self.sender.send(self0).unwrap();
-    }
-}
-
-struct Executor {
-    // 実行キュー
-    sender: SyncSender<Arc<Task>>,
-    receiver: Receiver<Arc<Task>>,
-}
-
-impl Executor {
-    fn new() -> Self {
-        // チャネルを生成
-        let (sender, receiver) = sync_channel(1024);
-        Executor {
-            sender: sender.clone(),
-            receiver,
-        }
-    }
-
-    fn get_spawner(&self) -> Spawner {
-        Spawner { sender: self.sender.clone() }
-    }
-
-    fn run(&self) {
-        // チャネルからTaskを受信して順に実行
-        while let Ok(task) = self.receiver.recv() {
-            // コンテキストを作成
-            let mut future = task.future.lock().unwrap();
-            let waker = waker_ref(&task);
-            let mut ctx = Context::from_waker(&waker);
-            // pollを呼び出し実行
-            let _ = future.as_mut().poll(&mut ctx);
-        }
-    }
-}
-
-struct Spawner {
-    sender: SyncSender<Arc<Task>>,
-}
-
-impl Spawner {
-    fn spawn(&self, future: impl Future<Output = ()> + 'static + Send) {
-        // FutureをBox化
-        let future = future.boxed();
-        // Task生成
-        let task = Arc::new(Task {    
-            future: Mutex::new(future),
-            sender: self.sender.clone(),
-        });
-
-        // 実行キューにエンキュー
-        self.sender.send(task).unwrap();
+// `cargo run -- timer`の場合はタイマの動作確認を実行
+fn main() {
+    if std::env::args().nth(1).as_deref() == Some("timer") {
+        timer();
+        return;
     }
-}
 
-fn main() {
     let executor = Executor::new();
     let selector = IOSelector::new();
     let spawner = executor.get_spawner();
@@ -390,3 +371,99 @@ fn main() {
     executor.get_spawner().spawn(server);
     executor.run();
 }
+
+const TOLERANCE: Duration = Duration::from_millis(50); // 起床の遅れの許容値
+const NUM_SLEEP: usize = 100;
+const NUM_TICK: u32 = 5;
+
+// 期限より早く起床せず、許容値以上に遅れないことを確認
+fn check_elapsed(elapsed: Duration, expected: Duration) {
+    assert!(elapsed >= expected, "{:?} < {:?}", elapsed, expected);
+    assert!(
+        elapsed < expected + TOLERANCE,
+        "{:?} >= {:?}",
+        elapsed,
+        expected + TOLERANCE
+    );
+}
+
+// sleep, interval, timeoutの精度とキャンセルを確認
+fn timer() {
+    let executor = Executor::new();
+    let selector = IOSelector::new();
+    let spawner = executor.get_spawner();
+
+    executor.block_on(async move {
+        // sleep
+        let dur = Duration::from_millis(100);
+        let start = Instant::now();
+        selector.sleep(dur).await;
+        check_elapsed(start.elapsed(), dur);
+        println!("sleep: {:?}", start.elapsed());
+
+        // 期限の異なる多数のsleepを同時に待機
+        // 期限の遅いタイマから登録し、epoll_waitのタイムアウトが再設定されることを確認
+        let start = Instant::now();
+        let handles: Vec<_> = (0..NUM_SLEEP)
+            .rev()
+            .map(|i| {
+                let selector = selector.clone();
+                let dur = Duration::from_millis(i as u64 + 1);
+                spawner.spawn(async move {
+                    selector.sleep(dur).await;
+                    check_elapsed(start.elapsed(), dur);
+                })
+            })
+            .collect();
+        for h in handles {
+            h.await.unwrap();
+        }
+        println!("sleep x {}: {:?}", NUM_SLEEP, start.elapsed());
+
+        // interval
+        let period = Duration::from_millis(20);
+        let start = Instant::now();
+        let mut interval = selector.interval(period);
+        let first = interval.tick().await;
+        check_elapsed(start.elapsed(), period);
+        for i in 1..NUM_TICK {
+            // 周期は起床の遅れに影響されない
+            let deadline = interval.tick().await;
+            assert_eq!(deadline, first + period * i);
+            check_elapsed(start.elapsed(), period * (i + 1));
+        }
+        println!("interval: {:?}", start.elapsed());
+
+        // timeout内に完了
+        let r = selector
+            .timeout(
+                Duration::from_millis(100),
+                selector.sleep(Duration::from_millis(10)),
+            )
+            .await;
+        assert_eq!(r, Ok(()));
+
+        // timeoutを過ぎた場合は、待機中のsleepもキャンセルされる
+        let dur = Duration::from_millis(50);
+        let start = Instant::now();
+        let r = selector
+            .timeout(dur, selector.sleep(Duration::from_secs(10)))
+            .await;
+        assert_eq!(r, Err(timer::Elapsed));
+        check_elapsed(start.elapsed(), dur);
+        assert_eq!(selector.num_timers(), 0);
+        println!("timeout: ok");
+
+        // 完了前にドロップしたsleepはキャンセルされる
+        let selector0 = selector.clone();
+        let handle = spawner.spawn(async move {
+            selector0.sleep(Duration::from_secs(10)).await;
+        });
+        selector.sleep(Duration::from_millis(10)).await;
+        assert_eq!(selector.num_timers(), 1);
+        handle.abort();
+        assert!(handle.await.unwrap_err().is_cancelled());
+        assert_eq!(selector.num_timers(), 0);
+        println!("cancel: ok");
+    });
+}
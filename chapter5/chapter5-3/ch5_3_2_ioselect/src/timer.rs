@@ -0,0 +1,280 @@
+use crate::IOSelector;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+// IOSelectorのスレッドが管理するタイマ
+// 期限の早い順に取り出すため、ヒープで管理する
+pub struct Timers {
+    // (期限, 識別子)のヒープ
+    // キャンセルされたタイマは、取り出す際に読み飛ばす
+    heap: BinaryHeap<Reverse<(Instant, u64)>>,
+    // 識別子からwaker
+    wakers: HashMap<u64, Waker>,
+    next_id: u64,
+}
+
+impl Timers {
+    pub fn new() -> Self {
+        Timers {
+            heap: BinaryHeap::new(),
+            wakers: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    // タイマを追加し、識別子と、それが最も早い期限になったかをリターン
+    pub fn add(&mut self, deadline: Instant, waker: Waker) -> (u64, bool) {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.heap.push(Reverse((deadline, id)));
+        self.wakers.insert(id, waker);
+        let earliest = self.heap.peek() == Some(&Reverse((deadline, id)));
+        (id, earliest)
+    }
+
+    // 起床するwakerを更新
+    pub fn update(&mut self, id: u64, waker: &Waker) {
+        if let Some(w) = self.wakers.get_mut(&id) {
+            if !w.will_wake(waker) {
+                *w = waker.clone();
+            }
+        }
+    }
+
+    pub fn cancel(&mut self, id: u64) {
+        self.wakers.remove(&id);
+    }
+
+    // 登録中のタイマの数
+    pub fn len(&self) -> usize {
+        self.wakers.len()
+    }
+
+    // 次の期限までのミリ秒。epoll_waitのタイムアウトに用いる
+    // 早く起床しないように切り上げる。タイマがない場合は-1
+    // epoll_waitはc_intにキャストするため、i32::MAXで制限する
+    // 制限した場合は早く起床するが、再度次の期限まで待機する
+    pub fn next_timeout(&mut self) -> isize {
+        while let Some(Reverse((deadline, id))) = self.heap.peek() {
+            if !self.wakers.contains_key(id) {
+                // キャンセル済み
+                self.heap.pop();
+                continue;
+            }
+            let d = deadline.saturating_duration_since(Instant::now());
+            return d.as_nanos().div_ceil(1_000_000).min(i32::MAX as u128) as isize;
+        }
+        -1
+    }
+
+    // 期限を過ぎたタイマのwakerを取り出す
+    pub fn expired(&mut self) -> Vec<Waker> {
+        let now = Instant::now();
+        let mut result = Vec::new();
+        while let Some(Reverse((deadline, id))) = self.heap.peek() {
+            if *deadline > now {
+                break;
+            }
+            if let Some(waker) = self.wakers.remove(id) {
+                result.push(waker);
+            }
+            self.heap.pop();
+        }
+        result
+    }
+}
+
+// 指定した時刻まで待機するFuture
+// 完了前にドロップすると、タイマはキャンセルされる
+pub struct Sleep {
+    deadline: Instant,
+    selector: Arc<IOSelector>,
+    id: Option<u64>, // 登録済みのタイマの識別子
+}
+
+impl Sleep {
+    // 期限を変更
+    fn reset(&mut self, deadline: Instant) {
+        if let Some(id) = self.id.take() {
+            self.selector.cancel_timer(id);
+        }
+        self.deadline = deadline;
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            if let Some(id) = self.id.take() {
+                self.selector.cancel_timer(id);
+            }
+            return Poll::Ready(());
+        }
+
+        match self.id {
+            // 期限をIOSelectorに登録
+            None => self.id = Some(self.selector.add_timer(self.deadline, cx.waker().clone())),
+            Some(id) => self.selector.update_timer(id, cx.waker()),
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            self.selector.cancel_timer(id);
+        }
+    }
+}
+
+// 一定周期で完了するtickを提供する型
+pub struct Interval {
+    sleep: Sleep,
+    period: Duration,
+}
+
+impl Interval {
+    // 次の周期まで待機し、その予定時刻をリターンするFuture
+    // 遅れた場合は、遅れを取り戻すまで待機せずに完了する
+    pub fn tick(&mut self) -> Tick<'_> {
+        Tick { interval: self }
+    }
+}
+
+pub struct Tick<'a> {
+    interval: &'a mut Interval,
+}
+
+impl<'a> Future for Tick<'a> {
+    type Output = Instant;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Instant> {
+        let interval = &mut *self.interval;
+        match Pin::new(&mut interval.sleep).poll(cx) {
+            Poll::Ready(()) => {
+                let deadline = interval.sleep.deadline;
+                interval.sleep.reset(deadline + interval.period);
+                Poll::Ready(deadline)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+// timeoutで期限を過ぎた場合のエラー
+#[derive(Debug, PartialEq, Eq)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("deadline has elapsed")
+    }
+}
+
+impl std::error::Error for Elapsed {}
+
+// 期限までにfutureが完了しなければエラーとなるFuture
+// 期限を過ぎた時点でfutureはドロップされる
+pub struct Timeout<F> {
+    future: Option<Pin<Box<F>>>,
+    sleep: Sleep,
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let Some(future) = self.future.as_mut() else {
+            return Poll::Ready(Err(Elapsed));
+        };
+        if let Poll::Ready(v) = future.as_mut().poll(cx) {
+            return Poll::Ready(Ok(v));
+        }
+        match Pin::new(&mut self.sleep).poll(cx) {
+            Poll::Ready(()) => {
+                // 待機中のfutureをキャンセル
+                self.future = None;
+                Poll::Ready(Err(Elapsed))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl IOSelector {
+    // durだけ待機するFutureをリターン
+    pub fn sleep(self: &Arc<Self>, dur: Duration) -> Sleep {
+        self.sleep_until(Instant::now() + dur)
+    }
+
+    pub fn sleep_until(self: &Arc<Self>, deadline: Instant) -> Sleep {
+        Sleep {
+            deadline,
+            selector: self.clone(),
+            id: None,
+        }
+    }
+
+    // 最初のtickが現在からperiod後に完了するIntervalをリターン
+    pub fn interval(self: &Arc<Self>, period: Duration) -> Interval {
+        assert!(!period.is_zero());
+        Interval {
+            sleep: self.sleep(period),
+            period,
+        }
+    }
+
+    // futureがdur以内に完了しなければElapsedとなるFutureをリターン
+    pub fn timeout<F: Future>(self: &Arc<Self>, dur: Duration, future: F) -> Timeout<F> {
+        Timeout {
+            future: Some(Box::pin(future)),
+            sleep: self.sleep(dur),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Timers;
+    use std::task::Waker;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn next_timeout() {
+        let mut timers = Timers::new();
+        assert_eq!(timers.next_timeout(), -1);
+
+        let (id, earliest) = timers.add(
+            Instant::now() + Duration::from_millis(100),
+            Waker::noop().clone(),
+        );
+        assert!(earliest);
+        let t = timers.next_timeout();
+        assert!(0 < t && t <= 100);
+
+        // キャンセルしたタイマは読み飛ばす
+        timers.cancel(id);
+        assert_eq!(timers.next_timeout(), -1);
+    }
+
+    #[test]
+    fn far_future() {
+        // c_intの範囲を超える期限でも、負の値や短い値にならない
+        let mut timers = Timers::new();
+        let deadline = Instant::now() + Duration::from_secs(365 * 24 * 60 * 60);
+        timers.add(deadline, Waker::noop().clone());
+        assert_eq!(timers.next_timeout(), i32::MAX as isize);
+        assert!(timers.expired().is_empty());
+    }
+}
@@ -0,0 +1,160 @@
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{fence, AtomicBool, Ordering};
+
+// スレッドの最大数
+pub const NUM_LOCK: usize = 8;
+
+// NUM_LOCKの余りを求めるためのビットマスク
+const MASK: usize = NUM_LOCK - 1;
+
+// 公平なロック用の型
+pub struct FairLock<T> {
+    registered: Vec<AtomicBool>, // スロットが使用中か
+    waiting: Vec<AtomicBool>,    // ロック獲得試行中のスレッド
+    lock: AtomicBool,            // ロック用変数
+    data: UnsafeCell<T>,         // 保護対象データ
+}
+
+// ロックのスロットを保持する型
+// 同じスロット番号で複数のスレッドが同時にロックすると排他できないため、
+// スロットはハンドルが排他的に所有し、スコープを外れると解放する
+pub struct FairLockHandle<'a, T> {
+    fair_lock: &'a FairLock<T>,
+    idx: usize, // スロット番号
+}
+
+// ロックの解放と、保護対象データへのアクセスを行うための型
+pub struct FairLockGuard<'a, T> {
+    fair_lock: &'a FairLock<T>,
+    idx: usize, // スレッド番号
+    // ガードは&mut Tとして振る舞うため、共有にはT: Syncを要求する
+    _marker: PhantomData<&'a mut T>,
+}
+
+impl<T> FairLock<T> {
+    pub fn new(v: T) -> Self {
+        let mut vec = Vec::new();
+        for _ in 0..NUM_LOCK {
+            vec.push(AtomicBool::new(false));
+        }
+
+        FairLock {
+            registered: (0..NUM_LOCK).map(|_| AtomicBool::new(false)).collect(),
+            waiting: vec,
+            lock: AtomicBool::new(false),
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    // 空いているスロットを確保してハンドルをリターン
+    // NUM_LOCK個のスロットがすべて使用中の場合はNone
+    pub fn register(&self) -> Option<FairLockHandle<'_, T>> {
+        for idx in 0..NUM_LOCK {
+            if self.registered[idx]
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                return Some(FairLockHandle {
+                    fair_lock: self,
+                    idx,
+                });
+            }
+        }
+        None
+    }
+
+    // ロック関数。idxはスロット番号
+    fn lock(&self, idx: usize) -> FairLockGuard<'_, T> {
+        // 自身のスレッドをロック獲得試行中に設定
+        self.waiting[idx].store(true, Ordering::Relaxed);
+        loop {
+            // 他のスレッドがfalseを設定した場合はロックを譲渡されている
+            if !self.waiting[idx].load(Ordering::Relaxed) {
+                break;
+            }
+
+            // 共有変数を用いてロック獲得を試みる
+            if !self.lock.load(Ordering::Relaxed)
+                && self
+                    .lock
+                    .compare_exchange_weak(false, true, Ordering::Relaxed, Ordering::Relaxed)
+                    .is_ok()
+            {
+                break;
+            }
+            std::hint::spin_loop();
+        }
+        fence(Ordering::Acquire);
+
+        FairLockGuard {
+            fair_lock: self,
+            idx,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> FairLockHandle<'a, T> {
+    // ロックを獲得
+    // ガードがハンドルを借用するため、同じスロットで二重にロックすることはない
+    pub fn lock(&mut self) -> FairLockGuard<'_, T> {
+        self.fair_lock.lock(self.idx)
+    }
+
+    // 確保したスロット番号
+    pub fn index(&self) -> usize {
+        self.idx
+    }
+}
+
+impl<'a, T> Drop for FairLockHandle<'a, T> {
+    // スロットを解放
+    fn drop(&mut self) {
+        self.fair_lock.registered[self.idx].store(false, Ordering::Release);
+    }
+}
+
+// ロック解放処理
+impl<'a, T> Drop for FairLockGuard<'a, T> {
+    fn drop(&mut self) {
+        let fl = self.fair_lock;
+
+        // 自身のスレッドを非ロック獲得試行中に設定
+        fl.waiting[self.idx].store(false, Ordering::Relaxed);
+
+        // 自分の次のスレッドから順に、ロック獲得試行中のスレッドを探す
+        for i in 1..NUM_LOCK {
+            let next = (self.idx + i) & MASK;
+            if fl.waiting[next].load(Ordering::Relaxed) {
+                // ロック用変数はtrueのまま、そのスレッドへ直接ロックを渡す
+                fl.waiting[next].store(false, Ordering::Release);
+                return;
+            }
+        }
+
+        // 待機中のスレッドがいない場合はロックを解放
+        fl.lock.store(false, Ordering::Release);
+    }
+}
+
+// FairLock型はスレッド間で共有可能と設定
+unsafe impl<T: Send> Sync for FairLock<T> {}
+unsafe impl<T: Send> Send for FairLock<T> {}
+
+// 保護対象データのimmutableな参照外し
+impl<'a, T> Deref for FairLockGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.fair_lock.data.get() }
+    }
+}
+
+// 保護対象データのmutableな参照外し
+impl<'a, T> DerefMut for FairLockGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.fair_lock.data.get() }
+    }
+}
@@ -1,3 +1,105 @@
+use std::sync::{Arc, Barrier};
+
+const NUM_LOOP: usize = 100000;
+const NUM_THREADS: usize = 4;
+
+mod fairlock;
+
 fn main() {
-    println!("Hello, world!");
+    let lock = Arc::new(fairlock::FairLock::new(0));
+    let mut v = Vec::new();
+
+    for _ in 0..NUM_THREADS {
+        let lock0 = lock.clone();
+        let t = std::thread::spawn(move || {
+            // 空いているスロットを確保
+            let mut handle = lock0.register().unwrap();
+            for _ in 0..NUM_LOOP {
+                let mut data = handle.lock();
+                *data += 1;
+            }
+        });
+        v.push(t);
+    }
+
+    for t in v {
+        t.join().unwrap();
+    }
+
+    let count = *lock.register().unwrap().lock();
+    println!("COUNT = {} (expected = {})", count, NUM_LOOP * NUM_THREADS);
+    assert_eq!(count, NUM_LOOP * NUM_THREADS);
+
+    starvation();
+    register();
+}
+
+// 高負荷時に飢餓状態となるスレッドがないことを確認
+fn starvation() {
+    // ロック獲得済みのスレッド、合計獲得回数、スレッド毎のロック獲得回数
+    let lock = Arc::new(fairlock::FairLock::new((
+        [false; NUM_THREADS],
+        0,
+        [0; NUM_THREADS],
+    )));
+    let barrier = Arc::new(Barrier::new(NUM_THREADS));
+    let mut v = Vec::new();
+
+    for i in 0..NUM_THREADS {
+        let lock0 = lock.clone();
+        let barrier0 = barrier.clone();
+        let t = std::thread::spawn(move || {
+            let mut handle = lock0.register().unwrap();
+            barrier0.wait();
+            loop {
+                let mut data = handle.lock();
+                // 合計獲得回数がNUM_LOOP * NUM_THREADSに達するまで獲得し続ける
+                if data.1 == NUM_LOOP * NUM_THREADS {
+                    break;
+                }
+                // 全スレッドが競合し始めてから計測する
+                data.0[i] = true;
+                if data.0.iter().all(|started| *started) {
+                    data.1 += 1;
+                    data.2[i] += 1;
+                }
+            }
+        });
+        v.push(t);
+    }
+
+    for t in v {
+        t.join().unwrap();
+    }
+
+    let (_, _, counts) = *lock.register().unwrap().lock();
+    println!("acquired = {:?}", counts);
+
+    // ロックは順番に受け渡されるため、どのスレッドもほぼ均等に獲得できる
+    for n in counts {
+        assert!(n >= NUM_LOOP / 2, "starvation: {:?}", counts);
+    }
+}
+
+// スロットの確保と解放を確認
+fn register() {
+    let lock = fairlock::FairLock::new(0);
+
+    // スロットをすべて確保すると、それ以上は確保できない
+    let mut handles: Vec<_> = (0..fairlock::NUM_LOCK)
+        .map(|_| lock.register().unwrap())
+        .collect();
+    assert!(lock.register().is_none());
+
+    // 解放したスロットは再利用される
+    let h = handles.remove(1);
+    assert_eq!(h.index(), 1);
+    drop(h);
+    let mut h = lock.register().unwrap();
+    assert_eq!(h.index(), 1);
+
+    *h.lock() += 1;
+    *handles[0].lock() += 1;
+    assert_eq!(*h.lock(), 2);
+    println!("register: ok");
 }
@@ -0,0 +1,3 @@
+mod ticketlock;
+
+pub use ticketlock::{TicketLock, TicketLockGuard};
@@ -1,12 +1,11 @@
+use ch7_1_2_ticketlock::TicketLock;
 use std::sync::Arc;
 
 const NUM_LOOP: usize = 100000;
 const NUM_THREADS: usize = 4;
 
-mod ticketlock;
-
 fn main() {
-    let lock = Arc::new(ticketlock::TicketLock::new(0));
+    let lock = Arc::new(TicketLock::new(0));
     let mut v = Vec::new();
 
     for _ in 0..NUM_THREADS {
@@ -29,4 +28,54 @@ fn main() {
         *lock.lock(),
         NUM_LOOP * NUM_THREADS
     );
+
+    try_lock();
+    guard();
+}
+
+// スレッド間で共有可能な型であることを、コンパイル時に検査
+fn is_send_sync<T: Send + Sync>() {}
+
+// ガードを介したデータへのアクセスを確認
+// Sendでないデータを共有できないことは、tests/uiのコンパイルエラーで検査
+fn guard() {
+    is_send_sync::<TicketLock<Vec<u64>>>();
+
+    let lock = TicketLock::new(vec![0u64; 4]);
+    {
+        let mut data = lock.lock();
+        data.push(1); // DerefMut
+        data[0] = 2;
+    }
+    {
+        let data = lock.try_lock().unwrap();
+        assert_eq!(data.len(), 5); // Deref
+        assert_eq!(*data, [2, 0, 0, 0, 1]);
+    }
+    println!("guard: ok");
+}
+
+// try_lockとwaitersの動作を確認
+fn try_lock() {
+    let lock = Arc::new(TicketLock::new(0));
+
+    let guard = lock.try_lock().unwrap();
+    assert_eq!(lock.waiters(), 0);
+    assert!(lock.try_lock().is_none());
+
+    // ロック中に別スレッドが待機
+    let lock0 = lock.clone();
+    let t = std::thread::spawn(move || {
+        *lock0.lock() += 1;
+    });
+    while lock.waiters() != 1 {
+        std::thread::yield_now();
+    }
+    // 解放すると、待機中のスレッドが獲得
+    drop(guard);
+    t.join().unwrap();
+
+    assert_eq!(lock.waiters(), 0);
+    assert_eq!(*lock.try_lock().unwrap(), 1);
+    println!("try_lock: ok");
 }
@@ -1,7 +1,11 @@
 use std::cell::UnsafeCell;
+use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
 use std::sync::atomic::{fence, AtomicUsize, Ordering};
 
+// 順番が1つ離れるごとにスピンする回数
+const BACKOFF_BASE: usize = 16;
+
 pub struct TicketLock<T> {
     ticket: AtomicUsize,
     turn: AtomicUsize,
@@ -10,6 +14,8 @@ pub struct TicketLock<T> {
 
 pub struct TicketLockGuard<'a, T> {
     ticket_lock: &'a TicketLock<T>,
+    // ガードは&mut Tとして振る舞うため、共有にはT: Syncを要求する
+    _marker: PhantomData<&'a mut T>,
 }
 
 impl<T> TicketLock<T> {
@@ -21,14 +27,55 @@ impl<T> TicketLock<T> {
         }
     }
 
-    pub fn lock(&self) -> TicketLockGuard<T> {
+    pub fn lock(&self) -> TicketLockGuard<'_, T> {
         // チケットを取得
         let t = self.ticket.fetch_add(1, Ordering::Relaxed);
         // 所有するチケットの順番になるまでスピン
-        while self.turn.load(Ordering::Relaxed) != t {}
+        loop {
+            let turn = self.turn.load(Ordering::Relaxed);
+            if turn == t {
+                break;
+            }
+            // 自分の順番までの距離に比例した回数だけ待機
+            let dist = t.wrapping_sub(turn);
+            for _ in 0..dist * BACKOFF_BASE {
+                std::hint::spin_loop();
+            }
+        }
         fence(Ordering::Acquire);
 
-        TicketLockGuard { ticket_lock: self }
+        TicketLockGuard {
+            ticket_lock: self,
+            _marker: PhantomData,
+        }
+    }
+
+    // 待機せずにロックの獲得を試みる
+    // 待機中のスレッドがおらず、次のチケットの順番である場合のみ獲得
+    pub fn try_lock(&self) -> Option<TicketLockGuard<'_, T>> {
+        // 前の所有者による解放を観測するためAcquire
+        let turn = self.turn.load(Ordering::Acquire);
+        self.ticket
+            .compare_exchange(
+                turn,
+                turn.wrapping_add(1),
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .ok()
+            .map(|_| TicketLockGuard {
+                ticket_lock: self,
+                _marker: PhantomData,
+            })
+    }
+
+    // ロックの獲得を待機しているスレッド数
+    // ロックを保持しているスレッドは含まない。監視用のため、値は概算
+    pub fn waiters(&self) -> usize {
+        // turnはticketを超えないため、turnを先に読み込む
+        let turn = self.turn.load(Ordering::Relaxed);
+        let ticket = self.ticket.load(Ordering::Relaxed);
+        ticket.wrapping_sub(turn).saturating_sub(1)
     }
 }
 
@@ -39,8 +86,8 @@ impl<'a, T> Drop for TicketLockGuard<'a, T> {
 }
 
 // TicketLock型はスレッド間で共有可能と設定
-unsafe impl<T> Sync for TicketLock<T> {}
-unsafe impl<T> Send for TicketLock<T> {}
+unsafe impl<T: Send> Sync for TicketLock<T> {}
+unsafe impl<T: Send> Send for TicketLock<T> {}
 
 impl<'a, T> Deref for TicketLockGuard<'a, T> {
     type Target = T;
@@ -55,3 +102,57 @@ impl<'a, T> DerefMut for TicketLockGuard<'a, T> {
         unsafe { &mut *self.ticket_lock.data.get() }
     }
 }
+
+// `cargo miri test`で検査できるよう、反復回数は小さくしている
+#[cfg(test)]
+mod tests {
+    use super::TicketLock;
+    use std::sync::Arc;
+
+    #[test]
+    fn guard_deref() {
+        let lock = TicketLock::new(vec![0u64; 4]);
+        {
+            let mut data = lock.lock();
+            data.push(1); // DerefMut
+            data[0] = 2;
+        }
+        let data = lock.lock();
+        assert_eq!(data.len(), 5); // Deref
+        assert_eq!(*data, [2, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn try_lock() {
+        let lock = TicketLock::new(0);
+        {
+            let mut data = lock.try_lock().unwrap();
+            assert!(lock.try_lock().is_none());
+            *data += 1;
+        }
+        assert_eq!(*lock.try_lock().unwrap(), 1);
+        assert_eq!(lock.waiters(), 0);
+    }
+
+    #[test]
+    fn counter() {
+        const NUM_THREADS: usize = 2;
+        const NUM_LOOP: usize = 50;
+
+        let lock = Arc::new(TicketLock::new(0));
+        let v: Vec<_> = (0..NUM_THREADS)
+            .map(|_| {
+                let lock = lock.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..NUM_LOOP {
+                        *lock.lock() += 1;
+                    }
+                })
+            })
+            .collect();
+        for t in v {
+            t.join().unwrap();
+        }
+        assert_eq!(*lock.lock(), NUM_THREADS * NUM_LOOP);
+    }
+}
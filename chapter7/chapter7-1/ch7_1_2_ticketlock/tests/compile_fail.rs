@@ -0,0 +1,6 @@
+// スレッド安全でない使い方がコンパイルエラーとなることを検査
+#[test]
+fn compile_fail() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
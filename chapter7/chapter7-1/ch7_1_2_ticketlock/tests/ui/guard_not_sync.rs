@@ -0,0 +1,11 @@
+// Cellはスレッド間で共有できないため、ガードの参照も共有できない
+use ch7_1_2_ticketlock::TicketLock;
+use std::cell::Cell;
+
+fn main() {
+    let lock = TicketLock::new(Cell::new(0));
+    let guard = lock.lock();
+    std::thread::scope(|s| {
+        s.spawn(|| guard.set(1));
+    });
+}
@@ -0,0 +1,12 @@
+// Rcは参照カウントを非アトミックに更新するため、ロックしても共有できない
+use ch7_1_2_ticketlock::TicketLock;
+use std::rc::Rc;
+use std::sync::Arc;
+
+fn main() {
+    let lock = Arc::new(TicketLock::new(Rc::new(0)));
+    let lock0 = lock.clone();
+    std::thread::spawn(move || {
+        let _rc = lock0.lock().clone();
+    });
+}
@@ -1,3 +1,35 @@
+use std::sync::Arc;
+
+const NUM_LOOP: usize = 100000;
+const NUM_THREADS: usize = 4;
+
+mod mcslock;
+
+use mcslock::{MCSLock, MCSNode};
+
 fn main() {
-    println!("Hello, world!");
+    let lock = Arc::new(MCSLock::new(0));
+    let mut v = Vec::new();
+
+    for _ in 0..NUM_THREADS {
+        let lock0 = lock.clone();
+        let t = std::thread::spawn(move || {
+            // スレッド毎に待機ノードを用意し、ロック獲得時に使い回す
+            let mut node = MCSNode::new();
+            for _ in 0..NUM_LOOP {
+                let mut data = lock0.lock(&mut node);
+                *data += 1;
+            }
+        });
+        v.push(t);
+    }
+
+    for t in v {
+        t.join().unwrap();
+    }
+
+    let mut node = MCSNode::new();
+    let count = *lock.lock(&mut node);
+    println!("COUNT = {} (expected = {})", count, NUM_LOOP * NUM_THREADS);
+    assert_eq!(count, NUM_LOOP * NUM_THREADS);
 }
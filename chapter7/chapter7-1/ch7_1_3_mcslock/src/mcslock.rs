@@ -0,0 +1,159 @@
+use std::cell::UnsafeCell;
+use std::ops::{Deref, DerefMut};
+use std::ptr::{null_mut, NonNull};
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+
+// MCSロック用の型
+pub struct MCSLock<T> {
+    last: AtomicPtr<Node>, // キューの最後尾
+    data: UnsafeCell<T>,   // 保護対象データ
+}
+
+// キューに繋がれる待機ノード
+struct Node {
+    next: AtomicPtr<Node>, // 次のノード
+    locked: AtomicBool,    // trueならロック獲得待ち
+}
+
+// スレッド毎に保持する待機ノード
+// ノード本体はヒープに確保し、キューに繋がれている間もアドレスが変わらないようにする
+pub struct MCSNode {
+    node: NonNull<Node>,
+    queued: bool, // キューに繋がれているか
+}
+
+// ロックの解放と、保護対象データへのアクセスを行うための型
+pub struct MCSLockGuard<'a, T> {
+    node: &'a mut MCSNode,    // 自スレッドのノード
+    mcs_lock: &'a MCSLock<T>, // キューの最後尾と保護対象データへの参照
+}
+
+impl MCSNode {
+    pub fn new() -> Self {
+        MCSNode {
+            node: Self::alloc(),
+            queued: false,
+        }
+    }
+
+    fn alloc() -> NonNull<Node> {
+        let node = Box::new(Node {
+            next: AtomicPtr::new(null_mut()),
+            locked: AtomicBool::new(false),
+        });
+        NonNull::from(Box::leak(node))
+    }
+}
+
+impl Default for MCSNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for MCSNode {
+    fn drop(&mut self) {
+        // ガードがmem::forgetされた場合、ノードは他スレッドから参照され続けるため解放しない
+        if !self.queued {
+            unsafe { drop(Box::from_raw(self.node.as_ptr())) };
+        }
+    }
+}
+
+impl<T> MCSLock<T> {
+    pub fn new(v: T) -> Self {
+        MCSLock {
+            last: AtomicPtr::new(null_mut()),
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    pub fn lock<'a>(&'a self, node: &'a mut MCSNode) -> MCSLockGuard<'a, T> {
+        // 前回のガードが解放されていない場合、古いノードは再利用せず新たに確保
+        if node.queued {
+            node.node = MCSNode::alloc();
+        }
+
+        // 自スレッド用のノードを初期化
+        let ptr = node.node.as_ptr();
+        let n = unsafe { &*ptr };
+        n.next.store(null_mut(), Ordering::Relaxed);
+        n.locked.store(true, Ordering::Relaxed);
+        node.queued = true;
+
+        // 自身をキューの最後尾とする
+        let prev = self.last.swap(ptr, Ordering::AcqRel);
+
+        // 最後尾がnullの場合は誰もロックを獲得しようとしていないためロック獲得
+        // null以外の場合は、自身をキューの最後尾に追加
+        if !prev.is_null() {
+            // 前のノードに自身を繋ぐ
+            unsafe { &*prev }.next.store(ptr, Ordering::Release);
+
+            // 自身のノードがfalseに設定されるまでスピン
+            // 各スレッドは自身のノードのみを監視するため、キャッシュラインの競合が起きない
+            while n.locked.load(Ordering::Acquire) {
+                std::hint::spin_loop();
+            }
+        }
+
+        MCSLockGuard {
+            node,
+            mcs_lock: self,
+        }
+    }
+}
+
+// ロック解放処理
+impl<'a, T> Drop for MCSLockGuard<'a, T> {
+    fn drop(&mut self) {
+        let ptr = self.node.node.as_ptr();
+        let n = unsafe { &*ptr };
+
+        // 自身の次のノードがnullかつ自身が最後尾のノードなら、最後尾をnullに設定
+        if n.next.load(Ordering::Acquire).is_null()
+            && self
+                .mcs_lock
+                .last
+                .compare_exchange(ptr, null_mut(), Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+        {
+            self.node.queued = false;
+            return;
+        }
+
+        // 自身の次のスレッドがlock関数実行中なので、その終了を待機
+        let mut next = n.next.load(Ordering::Acquire);
+        while next.is_null() {
+            std::hint::spin_loop();
+            next = n.next.load(Ordering::Acquire);
+        }
+
+        // 自身の次のスレッドを実行可能に設定
+        unsafe { &*next }.locked.store(false, Ordering::Release);
+        self.node.queued = false;
+    }
+}
+
+// MCSLock型はスレッド間で共有可能と設定
+unsafe impl<T: Send> Sync for MCSLock<T> {}
+unsafe impl<T: Send> Send for MCSLock<T> {}
+
+// MCSNodeは各スレッドで生成して使うが、スレッド間での移動は可能
+unsafe impl Send for MCSNode {}
+
+// 保護対象データのimmutableな参照外し
+impl<'a, T> Deref for MCSLockGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.mcs_lock.data.get() }
+    }
+}
+
+// 保護対象データのmutableな参照外し
+impl<'a, T> DerefMut for MCSLockGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.mcs_lock.data.get() }
+    }
+}
@@ -1,3 +1,188 @@
+use std::sync::Arc;
+use std::thread;
+
+#[macro_use]
+mod tm;
+
+use tm::{STMResult, TMem};
+
+const NUM_THREADS: usize = 4;
+const NUM_LOOP: usize = 10000;
+
+// 哲学者の数
+const NUM_PHILOSOPHERS: usize = 8;
+const NUM_EAT: usize = 1000;
+
+// 口座の数と初期残高
+const NUM_ACCOUNTS: usize = 8;
+const BALANCE: u64 = 1000;
+
 fn main() {
-    println!("Hello, world!");
+    counter();
+    transfer();
+    philosophers();
+}
+
+// 複数スレッドから同じワードをインクリメントし、更新が失われないことを確認
+fn counter() {
+    let mem = Arc::new(TMem::new(1));
+    let mut v = Vec::new();
+
+    for _ in 0..NUM_THREADS {
+        let mem0 = mem.clone();
+        let t = thread::spawn(move || {
+            for _ in 0..NUM_LOOP {
+                mem0.atomically(|tr| {
+                    let n = load!(tr, 0);
+                    store!(tr, 0, n + 1);
+                    STMResult::Ok(())
+                });
+            }
+        });
+        v.push(t);
+    }
+
+    for t in v {
+        t.join().unwrap();
+    }
+
+    let count = mem
+        .read_atomically(|tr| STMResult::Ok(load!(tr, 0)))
+        .unwrap();
+    println!("COUNT = {} (expected = {})", count, NUM_LOOP * NUM_THREADS);
+    assert_eq!(count as usize, NUM_LOOP * NUM_THREADS);
+}
+
+// 口座間の送金中に、読み込みトランザクションから見た残高の合計が常に一定であることを確認
+fn transfer() {
+    let mem = Arc::new(TMem::new(NUM_ACCOUNTS));
+    mem.atomically(|tr| {
+        for i in 0..NUM_ACCOUNTS {
+            store!(tr, i, BALANCE);
+        }
+        STMResult::Ok(())
+    });
+
+    let mut v = Vec::new();
+
+    // 送金用スレッド
+    for i in 0..NUM_THREADS {
+        let mem0 = mem.clone();
+        let t = thread::spawn(move || {
+            for j in 0..NUM_LOOP {
+                let from = (i + j) % NUM_ACCOUNTS;
+                let to = (i + j * 3 + 1) % NUM_ACCOUNTS;
+                let amount = (j % 10) as u64;
+                mem0.atomically(|tr| {
+                    let a = load!(tr, from);
+                    if a < amount {
+                        // 残高不足の場合は送金しない
+                        return STMResult::Abort;
+                    }
+                    store!(tr, from, a - amount);
+                    let b = load!(tr, to);
+                    store!(tr, to, b + amount);
+                    STMResult::Ok(())
+                });
+            }
+        });
+        v.push(t);
+    }
+
+    // 残高検査用スレッド
+    for _ in 0..NUM_THREADS {
+        let mem0 = mem.clone();
+        let t = thread::spawn(move || {
+            for _ in 0..NUM_LOOP {
+                let sum = mem0
+                    .read_atomically(|tr| {
+                        let mut sum = 0;
+                        for i in 0..NUM_ACCOUNTS {
+                            sum += load!(tr, i);
+                        }
+                        STMResult::Ok(sum)
+                    })
+                    .unwrap();
+                assert_eq!(sum, BALANCE * NUM_ACCOUNTS as u64);
+            }
+        });
+        v.push(t);
+    }
+
+    for t in v {
+        t.join().unwrap();
+    }
+
+    let sum = mem
+        .read_atomically(|tr| {
+            let mut sum = 0;
+            for i in 0..NUM_ACCOUNTS {
+                sum += load!(tr, i);
+            }
+            STMResult::Ok(sum)
+        })
+        .unwrap();
+    println!(
+        "SUM = {} (expected = {})",
+        sum,
+        BALANCE * NUM_ACCOUNTS as u64
+    );
+    assert_eq!(sum, BALANCE * NUM_ACCOUNTS as u64);
+}
+
+// STMを用いた食事する哲学者問題
+// 箸の値は0なら空き、それ以外は取り上げた哲学者の番号+1
+fn philosophers() {
+    let mem = Arc::new(TMem::new(NUM_PHILOSOPHERS));
+    let mut v = Vec::new();
+
+    for i in 0..NUM_PHILOSOPHERS {
+        let mem0 = mem.clone();
+        let t = thread::spawn(move || philosopher(mem0, i));
+        v.push(t);
+    }
+
+    for t in v {
+        t.join().unwrap();
+    }
+}
+
+fn philosopher(mem: Arc<TMem>, n: usize) {
+    let left = n;
+    let right = (n + 1) % NUM_PHILOSOPHERS;
+    let id = n as u64 + 1;
+
+    for _ in 0..NUM_EAT {
+        // 左右の箸を同時に取り上げる
+        // どちらかが使用中ならリトライするため、デッドロックは発生しない
+        mem.atomically(|tr| {
+            let c1 = load!(tr, left);
+            let c2 = load!(tr, right);
+            if c1 != 0 || c2 != 0 {
+                return STMResult::Retry;
+            }
+            store!(tr, left, id);
+            store!(tr, right, id);
+            STMResult::Ok(())
+        });
+
+        // 食事中は左右の箸を自分が保持している
+        let eating = mem
+            .read_atomically(|tr| {
+                let c1 = load!(tr, left);
+                let c2 = load!(tr, right);
+                STMResult::Ok(c1 == id && c2 == id)
+            })
+            .unwrap();
+        assert!(eating);
+
+        // 左右の箸を置く
+        mem.atomically(|tr| {
+            store!(tr, left, 0);
+            store!(tr, right, 0);
+            STMResult::Ok(())
+        });
+    }
+
+    println!("{}: finished eating", n);
 }
@@ -0,0 +1,282 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+
+// lock&versionの最上位ビットをロック用ビットとして使う
+const LOCK_BIT: u64 = 1 << 63;
+
+// トランザクショナルメモリの型
+// メモリはワード(u64)単位で、ワード毎にlock&versionを持つ
+pub struct TMem {
+    mem: Vec<AtomicU64>,      // メモリ
+    lock_ver: Vec<AtomicU64>, // lock&version
+    global_clock: AtomicU64,  // global version-clock
+}
+
+// トランザクションの結果
+pub enum STMResult<T> {
+    Ok(T),
+    Retry, // トランザクションをリトライ
+    Abort, // トランザクションを中止
+}
+
+impl TMem {
+    // sizeワードのメモリを0で初期化して確保
+    pub fn new(size: usize) -> Self {
+        let mut mem = Vec::new();
+        let mut lock_ver = Vec::new();
+        for _ in 0..size {
+            mem.push(AtomicU64::new(0));
+            lock_ver.push(AtomicU64::new(0));
+        }
+
+        TMem {
+            mem,
+            lock_ver,
+            global_clock: AtomicU64::new(0),
+        }
+    }
+
+    // 読み込みトランザクション
+    // リトライが必要な場合は成功するまで繰り返し、中止した場合はNoneをリターン
+    pub fn read_atomically<F, R>(&self, mut f: F) -> Option<R>
+    where
+        F: FnMut(&mut ReadTrans) -> STMResult<R>,
+    {
+        loop {
+            let mut tr = ReadTrans::new(self);
+            match f(&mut tr) {
+                STMResult::Abort => return None,
+                STMResult::Retry => (),
+                STMResult::Ok(val) => {
+                    // 読み込みのみなので、途中で競合していなければコミット不要
+                    if !tr.is_abort {
+                        return Some(val);
+                    }
+                }
+            }
+            std::hint::spin_loop();
+        }
+    }
+
+    // 書き込みトランザクション
+    // 競合した場合はコミットが成功するまでリトライし、中止した場合はNoneをリターン
+    pub fn atomically<F, R>(&self, mut f: F) -> Option<R>
+    where
+        F: FnMut(&mut WriteTrans) -> STMResult<R>,
+    {
+        loop {
+            let mut tr = WriteTrans::new(self);
+            match f(&mut tr) {
+                STMResult::Abort => return None,
+                STMResult::Retry => (),
+                STMResult::Ok(val) => {
+                    if !tr.is_abort && tr.commit() {
+                        return Some(val);
+                    }
+                }
+            }
+            std::hint::spin_loop();
+        }
+    }
+
+    // アドレスのlock&versionを読み込み、ロックされておらず
+    // かつバージョンがrv以下ならその値をリターン
+    fn read_ver(&self, addr: usize, rv: u64) -> Option<u64> {
+        let n = self.lock_ver[addr].load(Ordering::Acquire);
+        if n & LOCK_BIT != 0 || n > rv {
+            None
+        } else {
+            Some(n)
+        }
+    }
+
+    // アドレスの値を、lock&versionで一貫性を検査しながら読み込む
+    fn read_word(&self, addr: usize, rv: u64) -> Option<u64> {
+        let pre = self.read_ver(addr, rv)?;
+        let val = self.mem[addr].load(Ordering::Relaxed);
+        // 値の読み込みを、後続のバージョン読み込みより前に完了させる
+        fence(Ordering::Acquire);
+        let post = self.lock_ver[addr].load(Ordering::Relaxed);
+        if pre == post {
+            Some(val)
+        } else {
+            None
+        }
+    }
+
+    // アドレスのロックを獲得。既にロックされている場合はfalseをリターン
+    fn lock_addr(&self, addr: usize) -> bool {
+        let n = self.lock_ver[addr].fetch_or(LOCK_BIT, Ordering::Acquire);
+        n & LOCK_BIT == 0
+    }
+
+    // アドレスのロックを、バージョンを変えずに解放
+    fn unlock_addr(&self, addr: usize) {
+        self.lock_ver[addr].fetch_and(!LOCK_BIT, Ordering::Release);
+    }
+}
+
+// 読み込みトランザクション時に利用する型
+pub struct ReadTrans<'a> {
+    read_ver: u64,  // read-version
+    is_abort: bool, // 競合を検知した場合に真
+    mem: &'a TMem,
+}
+
+impl<'a> ReadTrans<'a> {
+    fn new(mem: &'a TMem) -> Self {
+        ReadTrans {
+            read_ver: mem.global_clock.load(Ordering::Acquire),
+            is_abort: false,
+            mem,
+        }
+    }
+
+    // メモリ読み込み関数。競合を検知した場合はNoneをリターン
+    pub fn load(&mut self, addr: usize) -> Option<u64> {
+        if self.is_abort {
+            return None;
+        }
+
+        let val = self.mem.read_word(addr, self.read_ver);
+        if val.is_none() {
+            self.is_abort = true;
+        }
+        val
+    }
+}
+
+// 書き込みトランザクション時に利用する型
+pub struct WriteTrans<'a> {
+    read_ver: u64,                  // read-version
+    read_set: HashSet<usize>,       // read-set
+    write_set: HashMap<usize, u64>, // write-set
+    locked: Vec<usize>,             // ロック済みアドレス
+    is_abort: bool,                 // 競合を検知した場合に真
+    mem: &'a TMem,
+}
+
+impl<'a> Drop for WriteTrans<'a> {
+    fn drop(&mut self) {
+        // ロック済みアドレスのロックを解放
+        for addr in self.locked.iter() {
+            self.mem.unlock_addr(*addr);
+        }
+    }
+}
+
+impl<'a> WriteTrans<'a> {
+    fn new(mem: &'a TMem) -> Self {
+        WriteTrans {
+            read_ver: mem.global_clock.load(Ordering::Acquire),
+            read_set: HashSet::new(),
+            write_set: HashMap::new(),
+            locked: Vec::new(),
+            is_abort: false,
+            mem,
+        }
+    }
+
+    // メモリ書き込み関数。コミットまではwrite-setに保存するのみ
+    pub fn store(&mut self, addr: usize, val: u64) {
+        assert!(addr < self.mem.mem.len());
+        self.write_set.insert(addr, val);
+    }
+
+    // メモリ読み込み関数。競合を検知した場合はNoneをリターン
+    pub fn load(&mut self, addr: usize) -> Option<u64> {
+        if self.is_abort {
+            return None;
+        }
+
+        // 既に書き込み済みならその値を読み込む
+        if let Some(val) = self.write_set.get(&addr) {
+            return Some(*val);
+        }
+
+        self.read_set.insert(addr);
+        let val = self.mem.read_word(addr, self.read_ver);
+        if val.is_none() {
+            self.is_abort = true;
+        }
+        val
+    }
+
+    // write-set中のアドレスのロックを獲得
+    fn lock_write_set(&mut self) -> bool {
+        for addr in self.write_set.keys() {
+            if self.mem.lock_addr(*addr) {
+                self.locked.push(*addr);
+            } else {
+                // 獲得済みのロックはDropで解放される
+                return false;
+            }
+        }
+        true
+    }
+
+    // read-setの検証
+    fn validate_read_set(&self) -> bool {
+        for addr in self.read_set.iter() {
+            let n = self.mem.lock_ver[*addr].load(Ordering::Acquire);
+            if n & LOCK_BIT != 0 && !self.write_set.contains_key(addr) {
+                // 他のトランザクションがロック中
+                return false;
+            }
+            if n & !LOCK_BIT > self.read_ver {
+                // 読み込み後に更新されている
+                return false;
+            }
+        }
+        true
+    }
+
+    // コミット
+    fn commit(&mut self) -> bool {
+        // 1. write-setのロックを獲得
+        if !self.lock_write_set() {
+            return false;
+        }
+
+        // 2. global version-clockをインクリメント
+        let write_ver = self.mem.global_clock.fetch_add(1, Ordering::AcqRel) + 1;
+
+        // 3. read-setの検証
+        // 他にコミットしたトランザクションがない場合は検証不要
+        if self.read_ver + 1 != write_ver && !self.validate_read_set() {
+            return false;
+        }
+
+        // 4. ロックの獲得後に書き込むように順序付け
+        fence(Ordering::Release);
+        for (addr, val) in self.write_set.iter() {
+            self.mem.mem[*addr].store(*val, Ordering::Relaxed);
+        }
+
+        // 5. バージョンを更新してロックを解放
+        for addr in self.locked.drain(..) {
+            self.mem.lock_ver[addr].store(write_ver, Ordering::Release);
+        }
+
+        true
+    }
+}
+
+// メモリ読み込み用のマクロ
+// 競合を検知した場合はトランザクションをリトライ
+macro_rules! load {
+    ($t:ident, $a:expr) => {
+        if let Some(v) = ($t).load($a) {
+            v
+        } else {
+            return $crate::tm::STMResult::Retry;
+        }
+    };
+}
+
+// メモリ書き込み用のマクロ
+macro_rules! store {
+    ($t:ident, $a:expr, $v:expr) => {
+        $t.store($a, $v)
+    };
+}
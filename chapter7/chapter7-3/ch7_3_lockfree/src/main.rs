@@ -1,3 +1,135 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+mod stack;
+
+use stack::Stack;
+
+const NUM_THREADS: usize = 4;
+const NUM_LOOP: usize = 100000;
+
+// 生成と破棄の回数
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+// 二重解放や解放漏れを検出するため、生成と破棄の回数を数える型
+struct Item(usize);
+
+impl Item {
+    fn new(n: usize) -> Self {
+        CREATED.fetch_add(1, Ordering::Relaxed);
+        Item(n)
+    }
+}
+
+impl Drop for Item {
+    fn drop(&mut self) {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// 擬似乱数(xorshift)
+fn xorshift(mut x: u64) -> u64 {
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    x
+}
+
 fn main() {
-    println!("Hello, world!");
+    stress();
+    bench();
+}
+
+// 各スレッドがランダムにpushとpopを行い、
+// すべての値がちょうど1回ずつ取り出されることを確認
+fn stress() {
+    let stack = Arc::new(Stack::<Item>::new());
+    let mut v = Vec::new();
+
+    for i in 0..NUM_THREADS {
+        let stack0 = stack.clone();
+        let t = std::thread::spawn(move || {
+            let mut popped = Vec::new();
+            let mut rnd = i as u64 + 1;
+            let mut n = 0;
+            while n < NUM_LOOP {
+                rnd = xorshift(rnd);
+                if rnd.is_multiple_of(3) {
+                    if let Some(item) = stack0.pop() {
+                        popped.push(item.0);
+                    }
+                } else {
+                    // スレッド番号と通し番号から一意な値を生成
+                    stack0.push(Item::new(i * NUM_LOOP + n));
+                    n += 1;
+                }
+            }
+            popped
+        });
+        v.push(t);
+    }
+
+    let mut popped = Vec::new();
+    for t in v {
+        popped.append(&mut t.join().unwrap());
+    }
+
+    // 残りを取り出す
+    while let Some(item) = stack.pop() {
+        popped.push(item.0);
+    }
+
+    popped.sort_unstable();
+    assert_eq!(popped.len(), NUM_THREADS * NUM_LOOP);
+    for (i, n) in popped.iter().enumerate() {
+        assert_eq!(i, *n);
+    }
+
+    // スタックの破棄後、すべての要素が1回ずつ破棄されている
+    drop(stack);
+    let created = CREATED.load(Ordering::Relaxed);
+    let dropped = DROPPED.load(Ordering::Relaxed);
+    println!("created = {}, dropped = {}", created, dropped);
+    assert_eq!(created, dropped);
+}
+
+// Mutex<Vec<T>>との性能比較
+fn bench() {
+    let stack = Arc::new(Stack::new());
+    let start = Instant::now();
+    let mut v = Vec::new();
+    for _ in 0..NUM_THREADS {
+        let stack0 = stack.clone();
+        let t = std::thread::spawn(move || {
+            for i in 0..NUM_LOOP {
+                stack0.push(i);
+                stack0.pop();
+            }
+        });
+        v.push(t);
+    }
+    for t in v {
+        t.join().unwrap();
+    }
+    println!("Stack:      {:?}", start.elapsed());
+
+    let vec = Arc::new(Mutex::new(Vec::new()));
+    let start = Instant::now();
+    let mut v = Vec::new();
+    for _ in 0..NUM_THREADS {
+        let vec0 = vec.clone();
+        let t = std::thread::spawn(move || {
+            for i in 0..NUM_LOOP {
+                vec0.lock().unwrap().push(i);
+                vec0.lock().unwrap().pop();
+            }
+        });
+        v.push(t);
+    }
+    for t in v {
+        t.join().unwrap();
+    }
+    println!("Mutex<Vec>: {:?}", start.elapsed());
 }
@@ -0,0 +1,215 @@
+use std::cell::UnsafeCell;
+use std::collections::HashSet;
+use std::mem::ManuallyDrop;
+use std::ptr::{self, null_mut};
+use std::sync::atomic::{fence, AtomicBool, AtomicPtr, Ordering};
+
+// 退避したノードがこの数を超えたら解放を試みる
+const SCAN_THRESHOLD: usize = 64;
+
+struct Node<T> {
+    // popで取り出すため、Nodeの解放時には破棄しない
+    data: ManuallyDrop<T>,
+    next: *mut Node<T>,
+}
+
+// ハザードポインタ
+// popするスレッドは、参照中のノードをハザードポインタとして公開し、
+// 他のスレッドはハザードポインタとして公開されているノードを解放しない
+struct HazardRecord<T> {
+    hazard: AtomicPtr<Node<T>>, // 参照中のノード
+    active: AtomicBool,         // いずれかのスレッドが使用中ならtrue
+    next: *mut HazardRecord<T>, // 次のレコード。リストに追加後は変更しない
+    // 解放待ちのノード。activeをtrueにしたスレッドのみがアクセスする
+    retired: UnsafeCell<Vec<*mut Node<T>>>,
+}
+
+// 使用中のHazardRecordを保持し、スコープを外れた際に返却するための型
+struct HazardGuard<'a, T> {
+    record: &'a HazardRecord<T>,
+}
+
+impl<'a, T> Drop for HazardGuard<'a, T> {
+    fn drop(&mut self) {
+        self.record.hazard.store(null_mut(), Ordering::Release);
+        self.record.active.store(false, Ordering::Release);
+    }
+}
+
+// ロックフリーなスタック
+pub struct Stack<T> {
+    head: AtomicPtr<Node<T>>,
+    records: AtomicPtr<HazardRecord<T>>, // HazardRecordのリスト
+}
+
+impl<T> Stack<T> {
+    pub fn new() -> Self {
+        Stack {
+            head: AtomicPtr::new(null_mut()),
+            records: AtomicPtr::new(null_mut()),
+        }
+    }
+
+    pub fn push(&self, v: T) {
+        let node = Box::into_raw(Box::new(Node {
+            data: ManuallyDrop::new(v),
+            next: null_mut(),
+        }));
+
+        loop {
+            // 先頭を自身の次のノードとし、CASで先頭を置き換える
+            let head = self.head.load(Ordering::Relaxed);
+            unsafe { (*node).next = head };
+            if self
+                .head
+                .compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                return;
+            }
+            std::hint::spin_loop();
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = self.acquire_record();
+        let record = guard.record;
+
+        let head = loop {
+            let head = self.head.load(Ordering::Acquire);
+            if head.is_null() {
+                return None;
+            }
+
+            // 先頭ノードをハザードポインタとして公開し、
+            // 公開後も先頭のままであれば、以降は解放されない
+            record.hazard.store(head, Ordering::SeqCst);
+            if self.head.load(Ordering::SeqCst) != head {
+                continue;
+            }
+
+            // ハザードポインタで保護しているため、解放済みのノードを読むことはない
+            // また、保護中は同じアドレスのノードが再確保されないため、ABA問題も起きない
+            let next = unsafe { (*head).next };
+            if self
+                .head
+                .compare_exchange_weak(head, next, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                break head;
+            }
+            std::hint::spin_loop();
+        };
+
+        record.hazard.store(null_mut(), Ordering::Release);
+
+        // 取り出したノードはスタックから外れているため、データを取り出せるのは自スレッドのみ
+        let data = unsafe { ptr::read(&*(*head).data) };
+
+        // ノードは他スレッドが参照中かもしれないため、退避して後で解放する
+        let retired = unsafe { &mut *record.retired.get() };
+        retired.push(head);
+        if retired.len() >= SCAN_THRESHOLD {
+            self.scan(retired);
+        }
+
+        Some(data)
+    }
+
+    // 未使用のHazardRecordを獲得。なければ新たに確保してリストに追加
+    fn acquire_record(&self) -> HazardGuard<'_, T> {
+        let mut p = self.records.load(Ordering::Acquire);
+        while !p.is_null() {
+            let record = unsafe { &*p };
+            if !record.active.load(Ordering::Relaxed)
+                && record
+                    .active
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return HazardGuard { record };
+            }
+            p = record.next;
+        }
+
+        let record = Box::into_raw(Box::new(HazardRecord {
+            hazard: AtomicPtr::new(null_mut()),
+            active: AtomicBool::new(true),
+            next: null_mut(),
+            retired: UnsafeCell::new(Vec::new()),
+        }));
+
+        loop {
+            let head = self.records.load(Ordering::Relaxed);
+            unsafe { (*record).next = head };
+            if self
+                .records
+                .compare_exchange_weak(head, record, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                return HazardGuard {
+                    record: unsafe { &*record },
+                };
+            }
+        }
+    }
+
+    // どのスレッドのハザードポインタにも含まれないノードを解放
+    fn scan(&self, retired: &mut Vec<*mut Node<T>>) {
+        // スタックからの取り外しを、ハザードポインタの読み込みより前に完了させる
+        fence(Ordering::SeqCst);
+
+        let mut hazards = HashSet::new();
+        let mut p = self.records.load(Ordering::Acquire);
+        while !p.is_null() {
+            let record = unsafe { &*p };
+            let h = record.hazard.load(Ordering::SeqCst);
+            if !h.is_null() {
+                hazards.insert(h);
+            }
+            p = record.next;
+        }
+
+        retired.retain(|node| {
+            if hazards.contains(node) {
+                true
+            } else {
+                // dataは取り出し済みのため、ノードのみ解放
+                unsafe { drop(Box::from_raw(*node)) };
+                false
+            }
+        });
+    }
+}
+
+impl<T> Default for Stack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for Stack<T> {
+    fn drop(&mut self) {
+        // スタックに残っているノードを、データごと解放
+        let mut p = *self.head.get_mut();
+        while !p.is_null() {
+            let mut node = unsafe { Box::from_raw(p) };
+            unsafe { ManuallyDrop::drop(&mut node.data) };
+            p = node.next;
+        }
+
+        // HazardRecordと、退避されたノードを解放
+        let mut p = *self.records.get_mut();
+        while !p.is_null() {
+            let record = unsafe { Box::from_raw(p) };
+            for node in record.retired.into_inner() {
+                unsafe { drop(Box::from_raw(node)) };
+            }
+            p = record.next;
+        }
+    }
+}
+
+// Stack型はスレッド間で共有可能と設定
+unsafe impl<T: Send> Sync for Stack<T> {}
+unsafe impl<T: Send> Send for Stack<T> {}